@@ -0,0 +1,34 @@
+use crate::config;
+use crate::listing::escape;
+
+fn is_local(host: &str) -> bool {
+    let name = crate::host_name(host).trim_matches(|c| c == '[' || c == ']');
+    name == "localhost"
+        || name.ends_with(".localhost")
+        || name
+            .parse::<::std::net::IpAddr>()
+            .map(|ip| ip.is_loopback())
+            .unwrap_or(false)
+}
+
+/// The tracking script for a request on `host`, none for local previews.
+pub fn snippet(analytics: &config::Analytics, host: Option<&str>) -> String {
+    if host.map(is_local).unwrap_or(true) {
+        return String::new();
+    }
+    let (url, site) = match (analytics.url.as_ref(), analytics.site.as_ref()) {
+        (Some(url), Some(site)) => (escape(url), escape(site)),
+        _ => return String::new(),
+    };
+    match analytics.provider.as_deref() {
+        Some("plausible") => format!(
+            "<script defer data-domain=\"{}\" src=\"{}\"></script>\n",
+            site, url
+        ),
+        Some("umami") => format!(
+            "<script defer data-website-id=\"{}\" src=\"{}\"></script>\n",
+            site, url
+        ),
+        _ => String::new(),
+    }
+}
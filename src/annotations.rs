@@ -0,0 +1,173 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+const ANNOTATIONS_CSS: &'static str = include_str!("html/annotations.css");
+const ANNOTATIONS_JS: &'static str = include_str!("html/annotations.js");
+
+/// Ends the name of the file holding a page's annotations.
+pub const SUFFIX: &str = ".annotations.json";
+/// Bounds on what one reader can pile onto a page.
+const MAX_PER_PAGE: usize = 1000;
+const MAX_BODY: usize = 4000;
+const MAX_QUOTE: usize = 1000;
+
+lazy_static! {
+    /// Each sidecar is read, changed and written back by one writer at a
+    /// time, those of other pages meanwhile by others.
+    static ref WRITING: Mutex<HashMap<PathBuf, Arc<Mutex<()>>>> = Mutex::new(HashMap::new());
+}
+
+/// A comment on a range of a page's text, found again by the text itself
+/// and a little of what surrounds it.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Annotation {
+    pub id: String,
+    pub user: String,
+    pub created: String,
+    pub quote: String,
+    #[serde(default)]
+    pub prefix: String,
+    #[serde(default)]
+    pub suffix: String,
+    pub body: String,
+    #[serde(default)]
+    pub resolved: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolved_by: Option<String>,
+}
+
+/// What a reader sends to annotate a page.
+#[derive(Deserialize)]
+pub struct New {
+    pub quote: String,
+    #[serde(default)]
+    pub prefix: String,
+    #[serde(default)]
+    pub suffix: String,
+    pub body: String,
+}
+
+/// `guide.md` keeps its annotations in `guide.annotations.json`.
+pub fn sidecar(page: &Path) -> PathBuf {
+    let stem = page
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    page.with_file_name(format!("{}{}", stem, SUFFIX))
+}
+
+pub fn is_sidecar(name: &str) -> bool {
+    name.ends_with(SUFFIX)
+}
+
+pub fn load(page: &Path) -> Vec<Annotation> {
+    ::std::fs::read(sidecar(page))
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+/// Calls `change` with the page's sidecar to itself.
+fn exclusive<T>(page: &Path, change: impl FnOnce() -> T) -> T {
+    let path = sidecar(page);
+    let lock = WRITING
+        .lock()
+        .expect("annotations lock poisoned")
+        .entry(path.clone())
+        .or_default()
+        .clone();
+    let changed = {
+        let _writing = lock.lock().expect("annotations lock poisoned");
+        change()
+    };
+    let mut writing = WRITING.lock().expect("annotations lock poisoned");
+    // the map's and ours: nobody else is writing nor waiting to
+    if Arc::strong_count(&lock) == 2 {
+        writing.remove(&path);
+    }
+    changed
+}
+
+/// Renamed into place, readers never see half a file. The temporary one
+/// is still a sidecar by its name, so it isn't served either.
+fn store(page: &Path, annotations: &[Annotation]) -> Result<(), String> {
+    let path = sidecar(page);
+    let json = serde_json::to_vec_pretty(annotations).map_err(|e| e.to_string())?;
+    let partial = path.with_file_name(format!(
+        ".{}",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    ::std::fs::write(&partial, json)
+        .and_then(|_| ::std::fs::rename(&partial, &path))
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn clip(s: &str, max: usize) -> String {
+    s.chars().take(max).collect()
+}
+
+pub fn add(page: &Path, user: &str, new: New) -> Result<Annotation, String> {
+    let quote = clip(new.quote.trim(), MAX_QUOTE);
+    let body = new.body.trim();
+    if quote.is_empty() || body.is_empty() {
+        return Err(String::from("an annotation needs a quote and a body"));
+    }
+    if body.chars().count() > MAX_BODY {
+        return Err(format!("annotations are at most {} characters", MAX_BODY));
+    }
+    exclusive(page, || {
+        let mut annotations = load(page);
+        if annotations.len() >= MAX_PER_PAGE {
+            return Err(String::from(
+                "this page has as many annotations as it takes",
+            ));
+        }
+        let created = chrono::Utc::now().to_rfc3339();
+        let digest =
+            Sha256::digest(format!("{}\n{}\n{}\n{}", user, created, quote, body).as_bytes());
+        let annotation = Annotation {
+            id: digest[..8].iter().map(|b| format!("{:02x}", b)).collect(),
+            user: String::from(user),
+            created,
+            quote,
+            prefix: clip(&new.prefix, 64),
+            suffix: clip(&new.suffix, 64),
+            body: String::from(body),
+            resolved: false,
+            resolved_by: None,
+        };
+        annotations.push(annotation.clone());
+        store(page, &annotations)?;
+        Ok(annotation)
+    })
+}
+
+/// Marks one resolved, giving it back unless there's no such annotation.
+pub fn resolve(page: &Path, id: &str, user: &str) -> Result<Option<Annotation>, String> {
+    exclusive(page, || {
+        let mut annotations = load(page);
+        let resolved = match annotations.iter_mut().find(|a| a.id == id) {
+            Some(annotation) => {
+                annotation.resolved = true;
+                annotation.resolved_by = Some(String::from(user));
+                annotation.clone()
+            }
+            None => return Ok(None),
+        };
+        store(page, &annotations)?;
+        Ok(Some(resolved))
+    })
+}
+
+/// Margin notes on the page, and a way to add some, for `url`'s annotations.
+pub fn widget(url: &str) -> String {
+    format!(
+        "<style>\n{}</style>\n<script data-annotations=\"{}\">\n{}</script>\n",
+        ANNOTATIONS_CSS,
+        crate::listing::escape(&format!("/_annotations{}", url)),
+        ANNOTATIONS_JS
+    )
+}
@@ -0,0 +1,117 @@
+use crate::frontmatter;
+use crate::render;
+use crate::symlinks::{self, Policy};
+use crate::template::Templates;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+pub struct Archive {
+    pub base_dir: PathBuf,
+    pub dir: PathBuf,
+    pub drafts: bool,
+    pub symlinks: Policy,
+    /// Pages go in as HTML rather than markdown.
+    pub rendered: Option<(
+        ::std::sync::Arc<render::Options>,
+        ::std::sync::Arc<Templates>,
+    )>,
+}
+
+/// Every file below `dir`, leaving out hidden ones and the links `policy`
+/// doesn't follow from `base_dir`.
+pub fn walk(policy: Policy, base_dir: &Path, dir: &Path, found: &mut Vec<PathBuf>) {
+    let entries = match ::std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        match symlinks::metadata(policy, base_dir, &entry) {
+            Some(meta) if meta.is_dir() => walk(policy, base_dir, &path, found),
+            Some(meta) if meta.is_file() => found.push(path),
+            _ => {}
+        }
+    }
+}
+
+/// What goes in the archive for one file, if anything.
+fn content(
+    archive: &Archive,
+    path: &Path,
+    rel: &Path,
+    visible: &impl Fn(&str) -> bool,
+) -> Option<(String, Vec<u8>)> {
+    let name = rel.to_string_lossy().replace('\\', "/");
+    let sidecar = rel.file_name().map_or(false, |n| {
+        crate::annotations::is_sidecar(&n.to_string_lossy())
+    });
+    if sidecar || (!archive.drafts && crate::is_draft_path(rel)) {
+        return None;
+    }
+    match path.strip_prefix(&archive.base_dir) {
+        Ok(from_base) if visible(&crate::index::url_for(from_base)) => {}
+        _ => return None,
+    }
+    if path.extension().map(|e| e != "md").unwrap_or(true) {
+        return ::std::fs::read(path).ok().map(|bytes| (name, bytes));
+    }
+
+    let input = crate::encoding::read_to_string(path).ok()?;
+    let (front_matter, body) = frontmatter::split(&input);
+    let draft = front_matter.is_draft();
+    let protected =
+        front_matter.get_str("protected").is_some() || front_matter.get_str("password").is_some();
+    if (draft && !archive.drafts) || protected {
+        return None;
+    }
+    match archive.rendered {
+        None => Some((name, input.into_bytes())),
+        Some((ref options, ref templates)) => {
+            let from_base = path.strip_prefix(&archive.base_dir).ok()?;
+            let page = crate::build::assemble(
+                &archive.base_dir,
+                options,
+                templates,
+                from_base,
+                &front_matter,
+                body,
+                "",
+            );
+            let name = format!("{}.html", name.strip_suffix(".md").unwrap_or(&name));
+            Some((name, page.into_bytes()))
+        }
+    }
+}
+
+/// Zips up a directory of the tree in memory, with only the files
+/// `visible` lets through.
+pub fn build(archive: &Archive, visible: impl Fn(&str) -> bool) -> Result<Vec<u8>, String> {
+    let mut files = Vec::new();
+    walk(
+        archive.symlinks,
+        &archive.base_dir,
+        &archive.dir,
+        &mut files,
+    );
+    files.sort();
+
+    let mut zip = zip::ZipWriter::new(::std::io::Cursor::new(Vec::new()));
+    let options =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    for path in files {
+        let rel = match path.strip_prefix(&archive.dir) {
+            Ok(rel) => rel,
+            Err(_) => continue,
+        };
+        if let Some((name, bytes)) = content(archive, &path, rel, &visible) {
+            zip.start_file(name, options).map_err(|e| e.to_string())?;
+            zip.write_all(&bytes).map_err(|e| e.to_string())?;
+        }
+    }
+    zip.finish()
+        .map(|cursor| cursor.into_inner())
+        .map_err(|e| e.to_string())
+}
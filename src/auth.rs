@@ -0,0 +1,292 @@
+use crate::config;
+use crate::frontmatter::FrontMatter;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::Path;
+
+pub struct Credentials {
+    pub user: String,
+    pub password: String,
+}
+
+/// Decodes an `Authorization: Basic …` header value.
+pub fn parse_basic(header: &str) -> Option<Credentials> {
+    let encoded = header.strip_prefix("Basic ")?.trim();
+    let decoded = String::from_utf8(base64::decode(encoded).ok()?).ok()?;
+    let mut parts = decoded.splitn(2, ':');
+    Some(Credentials {
+        user: String::from(parts.next()?),
+        password: String::from(parts.next()?),
+    })
+}
+
+pub struct User {
+    hash: String,
+    groups: Vec<String>,
+}
+
+/// Accounts read from a file of `user:bcrypt-hash[:group,group…]` lines,
+/// which is what `htpasswd -B` writes, plus an optional group list.
+#[derive(Default)]
+pub struct Users(HashMap<String, User>);
+
+impl Users {
+    pub fn load(path: &Path) -> ::std::io::Result<Users> {
+        let content = ::std::fs::read_to_string(path)?;
+        let users = content
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .filter_map(|line| {
+                let mut parts = line.splitn(3, ':');
+                let name = parts.next()?;
+                let hash = parts.next()?;
+                let groups = parts
+                    .next()
+                    .map(|g| g.split(',').map(|g| String::from(g.trim())).collect())
+                    .unwrap_or_default();
+                Some((
+                    String::from(name),
+                    User {
+                        hash: String::from(hash),
+                        groups,
+                    },
+                ))
+            })
+            .collect();
+        Ok(Users(users))
+    }
+
+    fn check(&self, credentials: &Credentials) -> Option<&User> {
+        let user = self.0.get(&credentials.user)?;
+        if bcrypt::verify(&credentials.password, &user.hash).unwrap_or(false) {
+            Some(user)
+        } else {
+            None
+        }
+    }
+}
+
+/// Who a request comes from, however we came to know.
+#[derive(Clone)]
+pub struct Identity {
+    pub user: String,
+    pub groups: Vec<String>,
+}
+
+impl Identity {
+    pub fn in_group(&self, group: &str) -> bool {
+        self.groups.iter().any(|g| g == group)
+    }
+}
+
+/// The account behind an `Authorization` header, if its password checks out.
+pub fn authenticate(users: &Users, authorization: Option<&str>) -> Option<Identity> {
+    let credentials = authorization.and_then(parse_basic)?;
+    users.check(&credentials).map(|user| Identity {
+        user: credentials.user.clone(),
+        groups: user.groups.clone(),
+    })
+}
+
+/// An address range, as in `10.0.0.0/8` or `::1/128`.
+#[derive(Clone, Copy)]
+pub struct Cidr {
+    network: IpAddr,
+    bits: u32,
+}
+
+impl Cidr {
+    pub fn parse(s: &str) -> Option<Cidr> {
+        let mut parts = s.splitn(2, '/');
+        let network: IpAddr = parts.next()?.parse().ok()?;
+        let max = if network.is_ipv4() { 32 } else { 128 };
+        let bits = match parts.next() {
+            Some(bits) => bits.parse().ok().filter(|b| *b <= max)?,
+            None => max,
+        };
+        Some(Cidr { network, bits })
+    }
+
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        fn prefix(bytes: &[u8], bits: u32) -> Vec<u8> {
+            bytes
+                .iter()
+                .enumerate()
+                .map(|(i, b)| {
+                    let keep = bits.saturating_sub(i as u32 * 8).min(8);
+                    if keep == 0 {
+                        0
+                    } else {
+                        b & (0xffu8 << (8 - keep))
+                    }
+                })
+                .collect()
+        }
+        match (self.network, addr) {
+            (IpAddr::V4(n), IpAddr::V4(a)) => {
+                prefix(&n.octets(), self.bits) == prefix(&a.octets(), self.bits)
+            }
+            (IpAddr::V6(n), IpAddr::V6(a)) => {
+                prefix(&n.octets(), self.bits) == prefix(&a.octets(), self.bits)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Identities set in request headers by an authenticating proxy in front.
+pub struct Proxy {
+    pub user_header: String,
+    pub groups_header: Option<String>,
+    /// Only requests from these addresses are taken at their word.
+    pub trusted: Vec<Cidr>,
+}
+
+impl Proxy {
+    fn identify(&self, headers: &http::HeaderMap, remote: Option<IpAddr>) -> Option<Identity> {
+        let remote = remote?;
+        if !self.trusted.iter().any(|cidr| cidr.contains(remote)) {
+            return None;
+        }
+        let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+        let user = header(&self.user_header)?.trim();
+        if user.is_empty() {
+            return None;
+        }
+        let groups = self
+            .groups_header
+            .as_ref()
+            .and_then(|name| header(name))
+            .map(|g| {
+                g.split(',')
+                    .map(|g| String::from(g.trim()))
+                    .filter(|g| !g.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+        Some(Identity {
+            user: String::from(user),
+            groups,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct Claims {
+    sub: String,
+    preferred_username: Option<String>,
+    #[serde(default)]
+    groups: Vec<String>,
+}
+
+/// Checks OIDC access tokens, `Authorization: Bearer …`, against the
+/// provider's public key.
+pub struct Bearer {
+    key: jsonwebtoken::DecodingKey<'static>,
+    validation: jsonwebtoken::Validation,
+}
+
+impl Bearer {
+    pub fn new(
+        key_file: &Path,
+        issuer: Option<&str>,
+        audience: Option<&str>,
+    ) -> Result<Bearer, String> {
+        let pem =
+            ::std::fs::read(key_file).map_err(|e| format!("{}: {}", key_file.display(), e))?;
+        let key = jsonwebtoken::DecodingKey::from_rsa_pem(&pem)
+            .map_err(|e| format!("{}: {}", key_file.display(), e))?
+            .into_static();
+        let mut validation = jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::RS256);
+        validation.iss = issuer.map(String::from);
+        if let Some(audience) = audience {
+            validation.set_audience(&[audience]);
+        }
+        Ok(Bearer { key, validation })
+    }
+
+    fn identify(&self, authorization: Option<&str>) -> Option<Identity> {
+        let token = authorization?.strip_prefix("Bearer ")?.trim();
+        let claims = jsonwebtoken::decode::<Claims>(token, &self.key, &self.validation)
+            .ok()?
+            .claims;
+        Some(Identity {
+            user: claims.preferred_username.unwrap_or(claims.sub),
+            groups: claims.groups,
+        })
+    }
+}
+
+/// Where identities may come from besides the users file.
+#[derive(Default)]
+pub struct Upstream {
+    pub proxy: Option<Proxy>,
+    pub bearer: Option<Bearer>,
+}
+
+/// Who's asking: the proxy's word first, then a bearer token, then a
+/// password from the users file.
+pub fn identify(
+    upstream: &Upstream,
+    users: &Users,
+    headers: &http::HeaderMap,
+    remote: Option<IpAddr>,
+) -> Option<Identity> {
+    let authorization = headers
+        .get(http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok());
+    upstream
+        .proxy
+        .as_ref()
+        .and_then(|proxy| proxy.identify(headers, remote))
+        .or_else(|| {
+            upstream
+                .bearer
+                .as_ref()
+                .and_then(|bearer| bearer.identify(authorization))
+        })
+        .or_else(|| authenticate(users, authorization))
+}
+
+/// Whether the front matter of a page lets this request through.
+///
+/// `protected: <group>` admits members of that group,
+/// `password: <bcrypt-hash>` admits anyone knowing the password.
+pub fn authorize(
+    front_matter: &FrontMatter,
+    identity: Option<&Identity>,
+    authorization: Option<&str>,
+) -> bool {
+    let group = front_matter.get_str("protected");
+    let hash = front_matter.get_str("password");
+    if group.is_none() && hash.is_none() {
+        return true;
+    }
+
+    let by_group = match (group, identity) {
+        (Some(group), Some(identity)) => identity.in_group(group),
+        _ => false,
+    };
+    let by_password = match (hash, authorization.and_then(parse_basic)) {
+        (Some(hash), Some(credentials)) => {
+            bcrypt::verify(&credentials.password, hash).unwrap_or(false)
+        }
+        _ => false,
+    };
+
+    by_group || by_password
+}
+
+/// Whether the config's `[[access]]` rules let `identity` see `path`:
+/// every rule whose prefix the path starts with wants one of its groups.
+pub fn allowed(rules: &[config::Access], path: &str, identity: Option<&Identity>) -> bool {
+    rules
+        .iter()
+        .filter(|rule| path.starts_with(&rule.prefix))
+        .all(|rule| match identity {
+            Some(identity) => rule.groups.iter().any(|g| identity.in_group(g)),
+            None => false,
+        })
+}
@@ -0,0 +1,142 @@
+use crate::frontmatter;
+use crate::index;
+use crate::listing::escape;
+use crate::mdbook;
+use crate::render;
+use crate::symlinks::{self, Policy};
+use crate::toc::{self, Heading};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+/// A directory read as one document, for `?book`.
+pub struct Book {
+    pub base_dir: PathBuf,
+    pub dir: PathBuf,
+    pub drafts: bool,
+    pub symlinks: Policy,
+    pub options: Arc<render::Options>,
+}
+
+fn is_chapter(rel: &Path) -> bool {
+    rel.extension().map(|e| e == "md").unwrap_or(false)
+        && rel
+            .file_name()
+            .map(|n| n != "SUMMARY.md" && n != "_header.md" && n != "_footer.md")
+            .unwrap_or(false)
+}
+
+/// Chapters as `SUMMARY.md` lists them, or every document below the
+/// directory by name when it has none.
+fn chapters(book: &Book) -> Vec<PathBuf> {
+    let dir = &book.dir;
+    if let Ok(summary) = ::std::fs::read_to_string(dir.join("SUMMARY.md")) {
+        return mdbook::summary_links(&summary)
+            .iter()
+            .map(|link| link.split('#').next().unwrap_or(""))
+            .map(|link| PathBuf::from(link.trim_start_matches("./")))
+            .filter(|rel| rel.components().all(|c| matches!(c, Component::Normal(_))))
+            .filter(|rel| is_chapter(rel) && dir.join(rel).is_file())
+            .filter(|rel| symlinks::allowed(book.symlinks, &book.base_dir, &dir.join(rel)))
+            .collect();
+    }
+    let mut files = Vec::new();
+    crate::archive::walk(book.symlinks, &book.base_dir, dir, &mut files);
+    let mut chapters: Vec<PathBuf> = files
+        .iter()
+        .filter_map(|path| path.strip_prefix(dir).ok())
+        .filter(|rel| is_chapter(rel))
+        .map(Path::to_path_buf)
+        .collect();
+    // a directory's index comes before what's in it
+    chapters.sort_by_key(|rel| {
+        let index = rel.file_stem().map(|s| s == "index").unwrap_or(false);
+        (rel.parent().map(Path::to_path_buf), !index, rel.clone())
+    });
+    chapters
+}
+
+/// The combined table of contents, nested by heading level.
+fn contents(toc: &[Heading]) -> String {
+    let mut out = String::from("<nav class=\"book-toc\">\n");
+    let mut levels: Vec<u32> = Vec::new();
+    for heading in toc {
+        while levels.last().map(|l| *l > heading.level).unwrap_or(false) {
+            levels.pop();
+            out.push_str("</li>\n</ul>\n");
+        }
+        match levels.last() {
+            Some(l) if *l == heading.level => out.push_str("</li>\n"),
+            _ => {
+                levels.push(heading.level);
+                out.push_str("<ul>\n");
+            }
+        }
+        out.push_str(&format!(
+            "<li><a href=\"#{}\">{}</a>",
+            escape(&heading.id),
+            escape(&heading.text)
+        ));
+    }
+    while levels.pop().is_some() {
+        out.push_str("</li>\n</ul>\n");
+    }
+    out.push_str("</nav>\n");
+    out
+}
+
+/// Every chapter `visible` lets through, in order, headings one level
+/// down under the book's title, after a table of contents for all of them.
+/// Drafts and protected pages stay out.
+pub fn render(book: &Book, visible: impl Fn(&str) -> bool) -> String {
+    let mut toc = Vec::new();
+    let mut sections = String::new();
+    for rel in chapters(book) {
+        let path = book.dir.join(&rel);
+        let url = match path.strip_prefix(&book.base_dir) {
+            Ok(from_base) => index::url_for(from_base),
+            Err(_) => continue,
+        };
+        if (!book.drafts && crate::is_draft_path(&rel)) || !visible(&url) {
+            continue;
+        }
+        let input = match crate::encoding::read_to_string(&path) {
+            Ok(input) => input,
+            Err(_) => continue,
+        };
+        let (front_matter, body) = frontmatter::split(&input);
+        let draft = front_matter.is_draft();
+        let protected = front_matter.get_str("protected").is_some()
+            || front_matter.get_str("password").is_some();
+        if (draft && !book.drafts) || protected {
+            continue;
+        }
+        let id = toc::slug(
+            &rel.with_extension("")
+                .to_string_lossy()
+                .replace(|c| c == '/' || c == '\\', "-"),
+        );
+        let (html, headings) = render::render_chapter(
+            body,
+            &book.options.for_page(&front_matter),
+            1,
+            &format!("{}-", id),
+        );
+        toc.extend(headings);
+        sections.push_str(&format!(
+            "<section class=\"chapter\" id=\"{}\">\n{}</section>\n",
+            escape(&id),
+            html
+        ));
+    }
+    let title = book
+        .dir
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| String::from("Book"));
+    format!(
+        "<div class=\"book\">\n<h1>{}</h1>\n{}{}</div>\n",
+        escape(&title),
+        contents(&toc),
+        sections
+    )
+}
@@ -0,0 +1,391 @@
+use crate::frontmatter::{self, FrontMatter};
+use crate::index::{self, Entry};
+use crate::links;
+use crate::render;
+use crate::template::{fill, Templates, Vars};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+pub struct Build {
+    pub base_dir: PathBuf,
+    pub out: PathBuf,
+    pub drafts: bool,
+    pub symlinks: crate::symlinks::Policy,
+    pub options: Arc<render::Options>,
+    pub templates: Arc<Templates>,
+    pub theme_dir: Option<PathBuf>,
+    pub schema: Arc<crate::config::Schema>,
+}
+
+/// Every file of the tree, relative to the base directory, with its mtime.
+type Snapshot = HashMap<PathBuf, SystemTime>;
+
+fn snapshot(build: &Build) -> Snapshot {
+    let mut files = Vec::new();
+    crate::archive::walk(build.symlinks, &build.base_dir, &build.base_dir, &mut files);
+    files
+        .into_iter()
+        .filter(|path| !path.starts_with(&build.out))
+        .filter_map(|path| {
+            let modified = ::std::fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+            let rel = path.strip_prefix(&build.base_dir).ok()?.to_path_buf();
+            Some((rel, modified))
+        })
+        .collect()
+}
+
+fn is_markdown(rel: &Path) -> bool {
+    rel.extension().map(|e| e == "md").unwrap_or(false)
+}
+
+fn is_partial(rel: &Path) -> bool {
+    rel.file_name()
+        .map(|n| n == "_header.md" || n == "_footer.md")
+        .unwrap_or(false)
+}
+
+fn output_path(build: &Build, rel: &Path) -> PathBuf {
+    if is_markdown(rel) {
+        build.out.join(rel.with_extension("html"))
+    } else {
+        build.out.join(rel)
+    }
+}
+
+fn partial(
+    base_dir: &Path,
+    options: &render::Options,
+    rel: &Path,
+    name: &str,
+    class: &str,
+) -> String {
+    let mut dir = base_dir.join(rel).parent().map(Path::to_path_buf);
+    while let Some(d) = dir {
+        if !d.starts_with(base_dir) {
+            break;
+        }
+        if let Ok(input) = crate::encoding::read_to_string(&d.join(name)) {
+            let (front_matter, body) = frontmatter::split(&input);
+            let (html, _) = render::render(body, &options.for_page(&front_matter));
+            return format!("<div class=\"{}\">\n{}</div>\n", class, html);
+        }
+        dir = d.parent().map(Path::to_path_buf);
+    }
+    String::new()
+}
+
+/// The exported page for a document, unless it stays out of the export.
+fn page(build: &Build, rel: &Path, entries: &[Entry]) -> Option<String> {
+    let input = crate::encoding::read_to_string(&build.base_dir.join(rel)).ok()?;
+    let (front_matter, body) = frontmatter::split(&input);
+    let draft = crate::is_draft_path(rel) || front_matter.is_draft();
+    let protected =
+        front_matter.get_str("protected").is_some() || front_matter.get_str("password").is_some();
+    if (draft && !build.drafts) || protected {
+        return None;
+    }
+    Some(assemble(
+        &build.base_dir,
+        &build.options,
+        &build.templates,
+        rel,
+        &front_matter,
+        body,
+        &links::section(&index::url_for(rel), entries),
+    ))
+}
+
+/// A document below `base_dir` as a standalone page, as exports have it:
+/// in the template its front matter picks, placeholders filled, with the
+/// section partials around it and `backlinks` after.
+pub fn assemble(
+    base_dir: &Path,
+    options: &render::Options,
+    templates: &Arc<Templates>,
+    rel: &Path,
+    front_matter: &FrontMatter,
+    body: &str,
+    backlinks: &str,
+) -> String {
+    let render::Document {
+        html,
+        words,
+        attachments,
+        ..
+    } = render::render_document(body, &options.for_page(front_matter));
+    let html = if front_matter
+        .get_bool("reading_time")
+        .unwrap_or(options.reading_time)
+    {
+        crate::reading::with_badge(&html, words)
+    } else {
+        html
+    };
+    let templates = front_matter
+        .get_str("template")
+        .and_then(|name| templates.named(name))
+        .unwrap_or_else(|| templates.clone());
+    let vars = Vars {
+        words: Some(words),
+        attachments: crate::template::attachments(&attachments),
+    };
+    [
+        crate::indexing::with_meta(
+            &fill(&templates.head(), &vars),
+            &crate::indexing::meta(front_matter),
+        )
+        .as_str(),
+        &partial(base_dir, options, rel, "_header.md", "section-header"),
+        &html,
+        &options.element_scripts(&html),
+        &partial(base_dir, options, rel, "_footer.md", "section-footer"),
+        backlinks,
+        &fill(&templates.tail(), &vars),
+    ]
+    .join("")
+}
+
+fn export(build: &Build, rel: &Path, entries: &[Entry]) -> Result<bool, String> {
+    let target = output_path(build, rel);
+    let content = if is_markdown(rel) {
+        match page(build, rel, entries) {
+            Some(page) => page.into_bytes(),
+            None => return remove(build, rel).map(|_| false),
+        }
+    } else {
+        let sidecar = rel.file_name().map_or(false, |n| {
+            crate::annotations::is_sidecar(&n.to_string_lossy())
+        });
+        if sidecar || (!build.drafts && crate::is_draft_path(rel)) {
+            return Ok(false);
+        }
+        ::std::fs::read(build.base_dir.join(rel)).map_err(|e| e.to_string())?
+    };
+    if let Some(parent) = target.parent() {
+        ::std::fs::create_dir_all(parent).map_err(|e| format!("{}: {}", parent.display(), e))?;
+    }
+    ::std::fs::write(&target, content).map_err(|e| format!("{}: {}", target.display(), e))?;
+    Ok(true)
+}
+
+fn remove(build: &Build, rel: &Path) -> Result<(), String> {
+    let target = output_path(build, rel);
+    match ::std::fs::remove_file(&target) {
+        Err(e) if e.kind() != ::std::io::ErrorKind::NotFound => {
+            Err(format!("{}: {}", target.display(), e))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn new_index() -> index::Index {
+    Arc::new(tokio::sync::Mutex::new(HashMap::new()))
+}
+
+fn scan(build: &Build, index: &index::Index) -> Result<Vec<Entry>, String> {
+    let mut rt = tokio::runtime::Runtime::new().map_err(|e| e.to_string())?;
+    let entries = rt.block_on(index::scan(&build.base_dir, build.symlinks, index));
+    Ok(index::published(entries, build.drafts))
+}
+
+/// Documents whose pages show something of `entry`: those it links to.
+fn linked(entry: &Entry, entries: &[Entry]) -> Vec<PathBuf> {
+    let from = entry.file_url();
+    let targets: HashSet<String> = entry
+        .links
+        .iter()
+        .filter_map(|href| links::resolve(&from, href))
+        .map(|url| String::from(url.trim_end_matches('/')))
+        .collect();
+    entries
+        .iter()
+        .filter(|e| targets.contains(e.file_url().trim_end_matches('/')))
+        .map(|e| e.path.clone())
+        .collect()
+}
+
+/// What to write again after `changed` files changed, given the entries
+/// from before and after.
+fn affected(changed: &[PathBuf], before: &[Entry], after: &[Entry]) -> HashSet<PathBuf> {
+    let mut affected = HashSet::new();
+    for rel in changed {
+        if is_partial(rel) {
+            let dir = rel.parent().unwrap_or_else(|| Path::new(""));
+            affected.extend(
+                after
+                    .iter()
+                    .filter(|e| e.path.starts_with(dir))
+                    .map(|e| e.path.clone()),
+            );
+            continue;
+        }
+        affected.insert(rel.clone());
+        if is_markdown(rel) {
+            // their backlinks list this page, by its old and new title
+            for entries in &[before, after] {
+                if let Some(entry) = entries.iter().find(|e| &e.path == rel) {
+                    affected.extend(linked(entry, after));
+                }
+            }
+        }
+    }
+    affected
+}
+
+/// Front matter not fitting the schema, in the pages going out.
+fn violations(build: &Build, files: &Snapshot) -> Vec<String> {
+    let mut pages: Vec<&PathBuf> = files
+        .keys()
+        .filter(|rel| is_markdown(rel) && !is_partial(rel))
+        .collect();
+    pages.sort();
+    let mut violations = Vec::new();
+    for rel in pages {
+        let input = match crate::encoding::read_to_string(&build.base_dir.join(rel)) {
+            Ok(input) => input,
+            Err(_) => continue,
+        };
+        let (front_matter, _) = frontmatter::split(&input);
+        let draft = crate::is_draft_path(rel) || front_matter.is_draft();
+        if draft && !build.drafts {
+            continue;
+        }
+        for message in crate::schema::validate(&build.schema, &front_matter) {
+            violations.push(format!("{}: {}", rel.display(), message));
+        }
+    }
+    violations
+}
+
+/// Exports the whole tree, pages rendered and other files copied, unless
+/// some page fails the front matter schema.
+pub fn run(build: &Build) -> Result<usize, String> {
+    let index = new_index();
+    let entries = scan(build, &index)?;
+    let files = snapshot(build);
+    let violations = violations(build, &files);
+    if !violations.is_empty() {
+        return Err(format!(
+            "{}\n{} front matter problems, nothing written",
+            violations.join("\n"),
+            violations.len()
+        ));
+    }
+    let mut written = 0;
+    for rel in files.keys() {
+        if !is_partial(rel) && export(build, rel, &entries)? {
+            written += 1;
+        }
+    }
+    Ok(written)
+}
+
+/// Exports one page as a self-contained HTML file, see `standalone`.
+pub fn single_file(build: &Build, rel: &Path) -> Result<PathBuf, String> {
+    let entries = scan(build, &new_index())?;
+    let html = page(build, rel, &entries).ok_or_else(|| {
+        format!(
+            "{}: no such page, or a draft or protected one",
+            rel.display()
+        )
+    })?;
+    let page_dir = build.base_dir.join(rel);
+    let html = crate::standalone::inline(
+        &html,
+        &crate::standalone::Files {
+            base_dir: &build.base_dir,
+            theme_dir: build.theme_dir.as_deref(),
+            page_dir: page_dir.parent().unwrap_or(&build.base_dir),
+        },
+    );
+    let target = output_path(build, rel);
+    if let Some(parent) = target.parent() {
+        ::std::fs::create_dir_all(parent).map_err(|e| format!("{}: {}", parent.display(), e))?;
+    }
+    ::std::fs::write(&target, html).map_err(|e| format!("{}: {}", target.display(), e))?;
+    Ok(target)
+}
+
+/// Pages dated in the future, with when they go out.
+fn scheduled(build: &Build, files: &Snapshot) -> Vec<(PathBuf, chrono::DateTime<chrono::Utc>)> {
+    if build.drafts {
+        return Vec::new();
+    }
+    files
+        .keys()
+        .filter(|rel| is_markdown(rel))
+        .filter_map(|rel| {
+            let input = crate::encoding::read_to_string(&build.base_dir.join(rel)).ok()?;
+            let at = frontmatter::split(&input).0.publish_at()?;
+            if at > chrono::Utc::now() {
+                Some((rel.clone(), at))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Exports the tree, then keeps polling it, writing again only the files
+/// that changed and the pages showing something of them, scheduled pages
+/// counting as changed when their time comes.
+pub fn watch(build: &Build) -> Result<(), String> {
+    println!("{} files written", run(build)?);
+    let index = new_index();
+    let mut entries = scan(build, &index)?;
+    let mut files = snapshot(build);
+    let mut pending = scheduled(build, &files);
+    loop {
+        ::std::thread::sleep(POLL_INTERVAL);
+        let current = snapshot(build);
+        let now = chrono::Utc::now();
+        let mut changed: Vec<PathBuf> = current
+            .iter()
+            .filter(|(rel, modified)| files.get(*rel) != Some(*modified))
+            .map(|(rel, _)| rel.clone())
+            .collect();
+        for (rel, _) in pending.iter().filter(|(_, at)| *at <= now) {
+            if !changed.contains(rel) {
+                changed.push(rel.clone());
+            }
+        }
+        let removed: Vec<PathBuf> = files
+            .keys()
+            .filter(|rel| !current.contains_key(*rel))
+            .cloned()
+            .collect();
+        if changed.is_empty() && removed.is_empty() {
+            continue;
+        }
+
+        let before = entries;
+        entries = scan(build, &index)?;
+        for rel in removed.iter() {
+            remove(build, rel)?;
+        }
+        let all: Vec<PathBuf> = changed.iter().chain(removed.iter()).cloned().collect();
+        let mut written = 0;
+        for rel in affected(&all, &before, &entries) {
+            if !current.contains_key(&rel) || is_partial(&rel) {
+                continue;
+            }
+            match export(build, &rel, &entries) {
+                Ok(true) => written += 1,
+                Ok(false) => {}
+                Err(err) => eprintln!("{}", err),
+            }
+        }
+        println!(
+            "{} changed, {} removed, {} files written",
+            changed.len(),
+            removed.len(),
+            written
+        );
+        pending = scheduled(build, &current);
+        files = current;
+    }
+}
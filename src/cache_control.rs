@@ -0,0 +1,55 @@
+use crate::config;
+use chrono::{Duration, Utc};
+use regex::Regex;
+
+pub struct Rule {
+    pattern: Regex,
+    value: http::HeaderValue,
+    max_age: Option<i64>,
+}
+
+/// `Cache-Control` policy for files served as is, first matching rule wins.
+#[derive(Default)]
+pub struct Rules(Vec<Rule>);
+
+fn max_age(value: &str) -> Option<i64> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter_map(|d| d.strip_prefix("max-age="))
+        .filter_map(|n| n.parse().ok())
+        .next()
+}
+
+impl Rules {
+    pub fn compile(rules: &[config::CacheRule]) -> Result<Rules, String> {
+        rules
+            .iter()
+            .map(|rule| {
+                let pattern = Regex::new(&rule.pattern).map_err(|e| e.to_string())?;
+                let value = http::HeaderValue::from_str(&rule.cache_control)
+                    .map_err(|_| format!("invalid Cache-Control: {}", rule.cache_control))?;
+                Ok(Rule {
+                    pattern,
+                    value,
+                    max_age: max_age(&rule.cache_control),
+                })
+            })
+            .collect::<Result<Vec<Rule>, String>>()
+            .map(Rules)
+    }
+
+    pub fn apply(&self, path: &str, headers: &mut http::HeaderMap) {
+        if let Some(rule) = self.0.iter().find(|r| r.pattern.is_match(path)) {
+            headers.insert(http::header::CACHE_CONTROL, rule.value.clone());
+            if let Some(seconds) = rule.max_age {
+                let expires = (Utc::now() + Duration::seconds(seconds))
+                    .format("%a, %d %b %Y %H:%M:%S GMT")
+                    .to_string();
+                if let Ok(expires) = http::HeaderValue::from_str(&expires) {
+                    headers.insert(http::header::EXPIRES, expires);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use crate::config;
+use crate::listing::escape;
+
+/// The comments section for the page at `url`, when the config puts one there.
+pub fn widget(comments: &config::Comments, url: &str) -> String {
+    if !comments.paths.iter().any(|p| url.starts_with(p.as_str())) {
+        return String::new();
+    }
+    let (src, prefix, defaults): (&str, &str, &[(&str, &str)]) = match comments.provider.as_deref()
+    {
+        Some("giscus") => (
+            "https://giscus.app/client.js",
+            "data-",
+            &[("mapping", "pathname")],
+        ),
+        Some("utterances") => (
+            "https://utteranc.es/client.js",
+            "",
+            &[("issue-term", "pathname")],
+        ),
+        _ => return String::new(),
+    };
+    let mut attributes: Vec<String> = comments
+        .params
+        .iter()
+        .map(|(name, value)| format!(" {}{}=\"{}\"", prefix, escape(name), escape(value)))
+        .collect();
+    for (name, value) in defaults {
+        if !comments.params.contains_key(*name) {
+            attributes.push(format!(" {}{}=\"{}\"", prefix, name, value));
+        }
+    }
+    attributes.sort();
+    format!(
+        "<section class=\"comments\">\n\
+         <script src=\"{}\"{} crossorigin=\"anonymous\" async></script>\n</section>\n",
+        src,
+        attributes.join("")
+    )
+}
@@ -0,0 +1,193 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Markdown extensions. Unset entries keep whatever the chosen preset does.
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct Markdown {
+    pub smart: Option<bool>,
+    pub footnotes: Option<bool>,
+    pub description_lists: Option<bool>,
+    /// Where code blocks with `file=` read from. Only the main config
+    /// file sets it, pages can't reach out of it.
+    pub snippets: Option<PathBuf>,
+}
+
+/// What the HTML sanitizer lets through beyond its defaults.
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct Sanitizer {
+    /// Hosts whose iframes, videos and sounds may be embedded,
+    /// `self` standing for relative URLs.
+    pub embeds: Vec<String>,
+    /// Custom elements, their names with a dash, and the attributes they keep.
+    pub elements: ::std::collections::BTreeMap<String, Vec<String>>,
+    /// Module scripts defining those elements, loaded by the pages using them.
+    pub scripts: Vec<String>,
+}
+
+/// A `Cache-Control` value for static files whose path matches `pattern`.
+#[derive(Deserialize, Clone)]
+pub struct CacheRule {
+    pub pattern: String,
+    pub cache_control: String,
+}
+
+/// Sends requests for `from` to `to`, with a 301 unless `status` says otherwise.
+#[derive(Deserialize, Clone)]
+pub struct Redirect {
+    pub from: String,
+    pub to: String,
+    pub status: Option<u16>,
+}
+
+/// Paths starting with `prefix` are for members of one of `groups`.
+#[derive(Deserialize, Clone)]
+pub struct Access {
+    pub prefix: String,
+    pub groups: Vec<String>,
+}
+
+/// How rendered pages are served.
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct Pages {
+    /// `Cache-Control` for rendered pages, `no-cache` when unset.
+    pub cache_control: Option<String>,
+    /// Theme directory for the pages of a subtree, in a `.mdserve.toml`.
+    pub theme: Option<PathBuf>,
+    /// A word count and reading time badge under the title.
+    pub reading_time: Option<bool>,
+    /// Jump between documents with `/` or Ctrl-K.
+    pub quick_switcher: Option<bool>,
+    /// Signed in readers comment on ranges of text, see `annotations`.
+    pub annotations: Option<bool>,
+}
+
+/// `mdserve lint` settings.
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct Lint {
+    /// Rule name to `off`, `warning` or `error`.
+    pub rules: HashMap<String, String>,
+}
+
+/// A giscus or utterances thread below the pages under some paths.
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct Comments {
+    /// `giscus` or `utterances`.
+    pub provider: Option<String>,
+    /// URL prefixes of the pages getting comments.
+    pub paths: Vec<String>,
+    /// Attributes of the provider's script, without giscus' `data-`.
+    pub params: ::std::collections::BTreeMap<String, String>,
+}
+
+/// The date at the bottom of pages.
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct LastModified {
+    /// `fs` (the default) for the file's mtime, `git` for its last commit, or `off`.
+    pub source: Option<String>,
+    /// chrono's strftime format, `%Y-%m-%d` by default.
+    pub format: Option<String>,
+    /// For month and day names, the page's language by default.
+    pub locale: Option<String>,
+    /// Text before the date, in the locale's words by default.
+    pub label: Option<String>,
+}
+
+/// A Plausible or umami script for the pages served to other hosts than localhost.
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct Analytics {
+    /// `plausible` or `umami`.
+    pub provider: Option<String>,
+    /// Where the script is.
+    pub url: Option<String>,
+    /// The domain for Plausible, the website id for umami.
+    pub site: Option<String>,
+}
+
+/// What the front matter of every page must look like.
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct Schema {
+    /// Keys every page sets.
+    pub required: Vec<String>,
+    /// Key to `string`, `boolean`, `number`, `date` or `list`.
+    pub fields: ::std::collections::BTreeMap<String, String>,
+    /// The tags pages may use, any when empty.
+    pub tags: Vec<String>,
+}
+
+/// How connections are handled, read once when the server starts.
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct Server {
+    /// `auto` for HTTP/1 and HTTP/2, told apart as they come, `off` or `only`.
+    /// There's no TLS, so HTTP/2 is cleartext h2c, which browsers never use:
+    /// it's for a TLS-terminating proxy in front.
+    pub http2: Option<String>,
+    /// HTTP/1 connections kept open between requests, the default.
+    pub keep_alive: Option<bool>,
+    /// Seconds an idle connection waits before TCP keepalive probes.
+    pub tcp_keepalive: Option<u64>,
+    pub tcp_nodelay: Option<bool>,
+    /// Connections open at once; those over it are answered 503.
+    pub max_connections: Option<usize>,
+    /// Concurrent streams of an HTTP/2 connection.
+    pub max_streams: Option<u32>,
+}
+
+/// Settings read from the file given with `--config`.
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct Config {
+    pub markdown: Markdown,
+    pub sanitizer: Sanitizer,
+    pub static_cache: Vec<CacheRule>,
+    pub pages: Pages,
+    pub redirects: Vec<Redirect>,
+    pub lint: Lint,
+    pub access: Vec<Access>,
+    pub comments: Comments,
+    pub analytics: Analytics,
+    pub last_modified: LastModified,
+    pub schema: Schema,
+    pub server: Server,
+}
+
+impl Config {
+    /// Layers `other` on top: what it sets wins, allowed embeds add up.
+    pub fn merge(&mut self, other: Config) {
+        let markdown = other.markdown;
+        self.markdown.smart = markdown.smart.or(self.markdown.smart);
+        self.markdown.footnotes = markdown.footnotes.or(self.markdown.footnotes);
+        self.markdown.description_lists = markdown
+            .description_lists
+            .or(self.markdown.description_lists);
+        self.sanitizer.embeds.extend(other.sanitizer.embeds);
+        self.sanitizer.elements.extend(other.sanitizer.elements);
+        self.sanitizer.scripts.extend(other.sanitizer.scripts);
+        let mut static_cache = other.static_cache;
+        static_cache.append(&mut self.static_cache);
+        self.static_cache = static_cache;
+        self.pages.cache_control = other
+            .pages
+            .cache_control
+            .or(self.pages.cache_control.take());
+        self.pages.theme = other.pages.theme.or(self.pages.theme.take());
+        self.pages.reading_time = other.pages.reading_time.or(self.pages.reading_time);
+        self.pages.quick_switcher = other.pages.quick_switcher.or(self.pages.quick_switcher);
+        self.pages.annotations = other.pages.annotations.or(self.pages.annotations);
+    }
+}
+
+pub fn load(path: &Path) -> Result<Config, String> {
+    let content =
+        ::std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    toml::from_str(&content).map_err(|e| format!("{}: {}", path.display(), e))
+}
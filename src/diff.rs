@@ -0,0 +1,224 @@
+use std::path::Path;
+use std::process::Command;
+
+/// Past this many token pairs the middle of two versions is shown as
+/// replaced wholesale rather than compared word by word.
+const MAX_CELLS: usize = 4_000_000;
+
+/// A `from` or `to` git accepts, never one it would take for an option.
+pub fn valid_rev(rev: &str) -> bool {
+    !rev.is_empty()
+        && !rev.starts_with('-')
+        && rev
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "._-/~^@{}".contains(c))
+}
+
+/// The file as it was at `rev`.
+pub fn at_rev(path: &Path, rev: &str) -> Result<String, String> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| String::from("not a file"))?
+        .to_string_lossy();
+    let output = Command::new("git")
+        .arg("show")
+        .arg(format!("{}:./{}", rev, name))
+        .current_dir(path.parent().unwrap_or_else(|| Path::new(".")))
+        .output()
+        .map_err(|e| format!("git: {}", e))?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+#[derive(PartialEq)]
+enum Token<'a> {
+    Tag(&'a str),
+    Space(&'a str),
+    Word(&'a str),
+}
+
+impl<'a> Token<'a> {
+    fn text(&self) -> &'a str {
+        match self {
+            Token::Tag(t) | Token::Space(t) | Token::Word(t) => t,
+        }
+    }
+}
+
+fn tokens(html: &str) -> Vec<Token<'_>> {
+    let mut found = Vec::new();
+    let mut rest = html;
+    while let Some(c) = rest.chars().next() {
+        let end = if c == '<' {
+            rest.find('>').map(|i| i + 1).unwrap_or(rest.len())
+        } else if c.is_whitespace() {
+            rest.find(|c: char| !c.is_whitespace())
+                .unwrap_or(rest.len())
+        } else {
+            rest.find(|c: char| c == '<' || c.is_whitespace())
+                .unwrap_or(rest.len())
+        };
+        let (token, tail) = rest.split_at(end);
+        found.push(match c {
+            '<' => Token::Tag(token),
+            c if c.is_whitespace() => Token::Space(token),
+            _ => Token::Word(token),
+        });
+        rest = tail;
+    }
+    found
+}
+
+enum Op {
+    Same,
+    Removed,
+    Added,
+}
+
+/// Longest common subsequence of the two, as the steps from one to the other.
+fn script(old: &[Token], new: &[Token]) -> Vec<Op> {
+    let (n, m) = (old.len(), new.len());
+    if n * m > MAX_CELLS {
+        return (0..n)
+            .map(|_| Op::Removed)
+            .chain((0..m).map(|_| Op::Added))
+            .collect();
+    }
+    // lengths[i][j]: common subsequence of old[i..] and new[j..]
+    let mut lengths = vec![0u32; (n + 1) * (m + 1)];
+    let at = |i: usize, j: usize| i * (m + 1) + j;
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lengths[at(i, j)] = if old[i] == new[j] {
+                lengths[at(i + 1, j + 1)] + 1
+            } else {
+                lengths[at(i + 1, j)].max(lengths[at(i, j + 1)])
+            };
+        }
+    }
+    let mut ops = Vec::with_capacity(n + m);
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && old[i] == new[j] {
+            ops.push(Op::Same);
+            i += 1;
+            j += 1;
+        } else if i < n && (j == m || lengths[at(i + 1, j)] >= lengths[at(i, j + 1)]) {
+            ops.push(Op::Removed);
+            i += 1;
+        } else {
+            ops.push(Op::Added);
+            j += 1;
+        }
+    }
+    ops
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mark {
+    None,
+    Del,
+    Ins,
+}
+
+struct Writer {
+    out: String,
+    mark: Mark,
+}
+
+impl Writer {
+    fn mark(&mut self, mark: Mark) {
+        if mark == self.mark {
+            return;
+        }
+        match self.mark {
+            Mark::Del => self.out.push_str("</del>"),
+            Mark::Ins => self.out.push_str("</ins>"),
+            Mark::None => {}
+        }
+        match mark {
+            Mark::Del => self.out.push_str("<del>"),
+            Mark::Ins => self.out.push_str("<ins>"),
+            Mark::None => {}
+        }
+        self.mark = mark;
+    }
+
+    fn text(&mut self, mark: Mark, text: &str) {
+        self.mark(mark);
+        self.out.push_str(text);
+    }
+}
+
+/// The new HTML with the words it lost in `<del>` and those it gained in
+/// `<ins>`. Markup follows the new version, the old one's is dropped.
+pub fn words(old: &str, new: &str) -> String {
+    let old = tokens(old);
+    let new = tokens(new);
+    let prefix = old
+        .iter()
+        .zip(new.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut writer = Writer {
+        out: String::new(),
+        mark: Mark::None,
+    };
+    for token in &new[..prefix] {
+        writer.out.push_str(token.text());
+    }
+    let (old_middle, new_middle) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+    let (mut i, mut j) = (0, 0);
+    for op in script(old_middle, new_middle) {
+        match op {
+            Op::Same => {
+                let token = &new_middle[j];
+                match token {
+                    Token::Space(_) if writer.mark != Mark::None => {
+                        // keeps "<del>a b</del>" in one piece
+                        writer.out.push_str(token.text())
+                    }
+                    _ => writer.text(Mark::None, token.text()),
+                }
+                i += 1;
+                j += 1;
+            }
+            Op::Removed => {
+                match old_middle[i] {
+                    Token::Tag(_) => {}
+                    Token::Space(space) if writer.mark == Mark::Del => writer.out.push_str(space),
+                    Token::Space(_) => {}
+                    Token::Word(word) => writer.text(Mark::Del, word),
+                }
+                i += 1;
+            }
+            Op::Added => {
+                match new_middle[j] {
+                    Token::Tag(tag) => writer.text(Mark::None, tag),
+                    Token::Space(space) if writer.mark == Mark::Ins => writer.out.push_str(space),
+                    Token::Space(space) => writer.text(Mark::None, space),
+                    Token::Word(word) => writer.text(Mark::Ins, word),
+                }
+                j += 1;
+            }
+        }
+    }
+    writer.mark(Mark::None);
+    for token in &new[new.len() - suffix..] {
+        writer.out.push_str(token.text());
+    }
+    writer.out
+}
@@ -0,0 +1,262 @@
+use crate::settings::{self, Sources};
+use crate::{config, frontmatter, index, lint, render};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Status {
+    Ok,
+    Warning,
+    Error,
+}
+
+pub struct Finding {
+    pub status: Status,
+    pub check: &'static str,
+    pub message: String,
+}
+
+fn finding(status: Status, check: &'static str, message: String) -> Finding {
+    Finding {
+        status,
+        check,
+        message,
+    }
+}
+
+/// What `mdserve doctor` looks at, as the server would be started.
+pub struct Checkup {
+    pub sources: Sources,
+    pub symlinks: crate::symlinks::Policy,
+    pub address: Option<String>,
+    pub pandoc: Option<PathBuf>,
+    pub upload_dir: Option<PathBuf>,
+}
+
+fn base_dir(dir: &Path, found: &mut Vec<Finding>) -> bool {
+    match ::std::fs::read_dir(dir) {
+        Ok(_) => {
+            found.push(finding(
+                Status::Ok,
+                "directory",
+                format!("{} is readable", dir.display()),
+            ));
+            true
+        }
+        Err(err) => {
+            found.push(finding(
+                Status::Error,
+                "directory",
+                format!(
+                    "{}: {}; give an existing, readable directory with --dir",
+                    dir.display(),
+                    err
+                ),
+            ));
+            false
+        }
+    }
+}
+
+fn uploads(dir: &Path, found: &mut Vec<Finding>) {
+    // the directory is created on the first upload, its parent has to take it
+    let existing = dir.ancestors().find(|d| d.exists()).unwrap_or(dir);
+    let writable = ::std::fs::metadata(existing)
+        .map(|m| !m.permissions().readonly())
+        .unwrap_or(false);
+    found.push(if writable {
+        finding(
+            Status::Ok,
+            "uploads",
+            format!("uploads can go to {}", dir.display()),
+        )
+    } else {
+        finding(
+            Status::Error,
+            "uploads",
+            format!(
+                "{} is not writable, uploads will fail; change its permissions or --upload-dir",
+                existing.display()
+            ),
+        )
+    })
+}
+
+fn documents(
+    dir: &Path,
+    symlinks: crate::symlinks::Policy,
+    options: &render::Options,
+    found: &mut Vec<Finding>,
+) {
+    let mut documents = Vec::new();
+    index::walk(symlinks, dir, dir, &mut documents);
+    documents.sort();
+    if documents.is_empty() {
+        found.push(finding(
+            Status::Warning,
+            "documents",
+            format!(
+                "no markdown files below {}, is it the right directory?",
+                dir.display()
+            ),
+        ));
+        return;
+    }
+    found.push(finding(
+        Status::Ok,
+        "documents",
+        format!("{} markdown files", documents.len()),
+    ));
+
+    let sample = documents
+        .iter()
+        .map(|(path, _)| path)
+        .find(|path| path.file_name().map(|n| n == "index.md").unwrap_or(false))
+        .unwrap_or(&documents[0].0);
+    let rendered = ::std::fs::read_to_string(sample)
+        .map_err(|e| e.to_string())
+        .and_then(|input| {
+            ::std::panic::catch_unwind(|| {
+                let (front_matter, body) = frontmatter::split(&input);
+                render::render(body, &options.for_page(&front_matter)).0
+            })
+            .map_err(|_| String::from("the renderer panicked"))
+        });
+    found.push(match rendered {
+        Ok(html) => finding(
+            Status::Ok,
+            "render",
+            format!("{} renders to {} bytes", sample.display(), html.len()),
+        ),
+        Err(err) => finding(
+            Status::Error,
+            "render",
+            format!("{}: {}", sample.display(), err),
+        ),
+    });
+}
+
+fn address(address: &str, found: &mut Vec<Finding>) {
+    let parsed: ::std::net::SocketAddr = match address.parse() {
+        Ok(addr) => addr,
+        Err(_) => {
+            found.push(finding(
+                Status::Error,
+                "address",
+                format!("{}: not an address, give one like 127.0.0.1:8000", address),
+            ));
+            return;
+        }
+    };
+    found.push(match ::std::net::TcpListener::bind(parsed) {
+        Ok(_) => finding(Status::Ok, "address", format!("{} is free", parsed)),
+        Err(err) => finding(
+            Status::Error,
+            "address",
+            format!(
+                "cannot listen on {}: {}; is another server running there?",
+                parsed, err
+            ),
+        ),
+    });
+}
+
+fn pandoc(pandoc: &Path, found: &mut Vec<Finding>) {
+    let runs = ::std::process::Command::new(pandoc)
+        .arg("--version")
+        .output()
+        .map(|o| o.status.success())
+        .unwrap_or(false);
+    found.push(if runs {
+        finding(Status::Ok, "pandoc", format!("{} runs", pandoc.display()))
+    } else {
+        finding(
+            Status::Error,
+            "pandoc",
+            format!(
+                "{} does not run, install pandoc or fix --pandoc",
+                pandoc.display()
+            ),
+        )
+    });
+}
+
+/// Every check, in the order a start would run into its problem.
+pub fn run(checkup: &Checkup) -> Vec<Finding> {
+    let mut found = Vec::new();
+    let settings = match settings::load(&checkup.sources) {
+        Ok(settings) => {
+            found.push(finding(
+                Status::Ok,
+                "config",
+                String::from("configuration and users files read"),
+            ));
+            Some(settings)
+        }
+        Err(err) => {
+            found.push(finding(
+                Status::Error,
+                "config",
+                format!("{}; fix it or leave out the option naming it", err),
+            ));
+            None
+        }
+    };
+    if let Some(Err(err)) = checkup.sources.config.as_ref().map(|path| {
+        config::load(path).and_then(|config| lint::Levels::new(&config.lint).map(|_| ()))
+    }) {
+        found.push(finding(Status::Warning, "lint", err));
+    }
+    if let Some(theme) = checkup.sources.theme_dir.as_ref() {
+        if !theme.is_dir() {
+            found.push(finding(
+                Status::Error,
+                "theme",
+                format!(
+                    "{} is not a directory, the built-in look is used",
+                    theme.display()
+                ),
+            ));
+        }
+    }
+
+    if base_dir(&checkup.sources.base_dir, &mut found) {
+        let options = settings
+            .as_ref()
+            .map(|s| (*s.options).clone())
+            .unwrap_or_default();
+        documents(
+            &checkup.sources.base_dir,
+            checkup.symlinks,
+            &options,
+            &mut found,
+        );
+        if let Some(upload_dir) = checkup.upload_dir.as_ref() {
+            uploads(&checkup.sources.base_dir.join(upload_dir), &mut found);
+        }
+    }
+    if let Some(path) = checkup.pandoc.as_ref() {
+        pandoc(path, &mut found);
+    }
+    if let Some(addr) = checkup.address.as_ref() {
+        address(addr, &mut found);
+    }
+    found
+}
+
+pub fn report(found: &[Finding]) -> String {
+    found
+        .iter()
+        .map(|f| {
+            format!(
+                "{:7} {:9} {}\n",
+                match f.status {
+                    Status::Ok => "ok",
+                    Status::Warning => "warning",
+                    Status::Error => "error",
+                },
+                f.check,
+                f.message
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,98 @@
+use std::fmt;
+use std::path::Path;
+
+/// Bytes that are none of the encodings we know, with where they stop
+/// making sense.
+#[derive(Debug, Clone)]
+pub struct Undecodable {
+    pub offset: usize,
+    pub reason: &'static str,
+}
+
+impl fmt::Display for Undecodable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.reason, self.offset)
+    }
+}
+
+fn utf16(bytes: &[u8], little_endian: bool) -> Result<String, Undecodable> {
+    if bytes.len() % 2 != 0 {
+        return Err(Undecodable {
+            offset: bytes.len() + 1,
+            reason: "UTF-16 cut short",
+        });
+    }
+    let units = bytes.chunks(2).map(|pair| {
+        if little_endian {
+            u16::from_le_bytes([pair[0], pair[1]])
+        } else {
+            u16::from_be_bytes([pair[0], pair[1]])
+        }
+    });
+    let mut out = String::with_capacity(bytes.len() / 2);
+    let mut offset = 2;
+    for c in ::std::char::decode_utf16(units) {
+        match c {
+            Ok(c) => {
+                out.push(c);
+                offset += c.len_utf16() * 2;
+            }
+            Err(_) => {
+                return Err(Undecodable {
+                    offset,
+                    reason: "unpaired UTF-16 surrogate",
+                })
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Control characters no text file has, and the C1 range, which in
+/// practice means windows-1252 or binary rather than latin-1.
+fn is_latin1(bytes: &[u8]) -> bool {
+    bytes.iter().all(|&b| match b {
+        b'\t' | b'\n' | b'\r' | 0x0c => true,
+        0x00..=0x1f | 0x7f..=0x9f => false,
+        _ => true,
+    })
+}
+
+/// Text as UTF-8: as is, without its BOM, from UTF-16 with a BOM, or
+/// from latin-1 when it can't be UTF-8.
+pub fn decode(bytes: Vec<u8>) -> Result<String, Undecodable> {
+    if bytes.starts_with(&[0xef, 0xbb, 0xbf]) {
+        return String::from_utf8(bytes[3..].to_vec()).map_err(|e| Undecodable {
+            offset: e.utf8_error().valid_up_to() + 3,
+            reason: "invalid UTF-8",
+        });
+    }
+    if bytes.starts_with(&[0xff, 0xfe]) {
+        return utf16(&bytes[2..], true);
+    }
+    if bytes.starts_with(&[0xfe, 0xff]) {
+        return utf16(&bytes[2..], false);
+    }
+    match String::from_utf8(bytes) {
+        Ok(text) => Ok(text),
+        Err(err) => {
+            let offset = err.utf8_error().valid_up_to();
+            let bytes = err.into_bytes();
+            if is_latin1(&bytes) {
+                Ok(bytes.iter().map(|&b| b as char).collect())
+            } else {
+                Err(Undecodable {
+                    offset,
+                    reason: "neither UTF-8, UTF-16 with a BOM nor latin-1",
+                })
+            }
+        }
+    }
+}
+
+/// `fs::read_to_string` for documents, see `decode`.
+pub fn read_to_string(path: &Path) -> ::std::io::Result<String> {
+    let bytes = ::std::fs::read(path)?;
+    decode(bytes)
+        .map_err(|e| ::std::io::Error::new(::std::io::ErrorKind::InvalidData, e.to_string()))
+}
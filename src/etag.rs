@@ -0,0 +1,41 @@
+use sha2::{Digest, Sha256};
+
+/// A strong entity tag for `bytes`, quotes included.
+pub fn of(bytes: &[u8]) -> String {
+    let digest = Sha256::digest(bytes);
+    let hex: String = digest[..16].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("\"{}\"", hex)
+}
+
+/// Whether an `If-None-Match` header lets us answer 304 for `etag`.
+pub fn matches(if_none_match: Option<&str>, etag: &str) -> bool {
+    match if_none_match {
+        Some(header) => header
+            .split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag == etag || tag.strip_prefix("W/") == Some(etag)),
+        None => false,
+    }
+}
+
+/// Turns a response carrying an `ETag` into a bodyless 304 when the client
+/// already holds that version. Caching headers are kept, as RFC 7232 asks.
+pub fn conditional(
+    if_none_match: Option<&str>,
+    response: warp::reply::Response,
+) -> warp::reply::Response {
+    let fresh = response
+        .headers()
+        .get(http::header::ETAG)
+        .and_then(|tag| tag.to_str().ok())
+        .map(|tag| matches(if_none_match, tag))
+        .unwrap_or(false);
+    if !fresh {
+        return response;
+    }
+    let (mut parts, _) = response.into_parts();
+    parts.status = http::StatusCode::NOT_MODIFIED;
+    parts.headers.remove(http::header::CONTENT_TYPE);
+    parts.headers.remove(http::header::CONTENT_LENGTH);
+    warp::reply::Response::from_parts(parts, Default::default())
+}
@@ -0,0 +1,156 @@
+use crate::frontmatter::{self, FrontMatter};
+use crate::index;
+use crate::symlinks::Policy;
+use chrono::Utc;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tokio::sync::broadcast;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How many changes a slow subscriber may fall behind before missing some.
+const BACKLOG: usize = 256;
+
+#[derive(Clone, Serialize)]
+pub struct Change {
+    /// `created`, `modified` or `deleted`.
+    pub kind: &'static str,
+    /// Relative to the base directory.
+    pub path: String,
+    pub url: String,
+    /// The page's, for telling who may hear of it.
+    #[serde(skip)]
+    pub front_matter: FrontMatter,
+}
+
+/// Changes to the documents of a tree, for `/_events` subscribers.
+pub struct Events {
+    sender: broadcast::Sender<Change>,
+}
+
+impl Default for Events {
+    fn default() -> Events {
+        let (sender, _) = broadcast::channel(BACKLOG);
+        Events { sender }
+    }
+}
+
+impl Events {
+    pub fn subscribe(&self) -> broadcast::Receiver<Change> {
+        self.sender.subscribe()
+    }
+}
+
+type Snapshot = HashMap<PathBuf, (SystemTime, FrontMatter)>;
+
+/// The front matter of documents, read again only when they change.
+type Schedule = HashMap<PathBuf, (SystemTime, FrontMatter)>;
+
+fn front_matter(path: &Path, modified: SystemTime, schedule: &mut Schedule) -> FrontMatter {
+    if let Some((m, front_matter)) = schedule.get(path) {
+        if *m == modified {
+            return front_matter.clone();
+        }
+    }
+    let front_matter = crate::encoding::read_to_string(path)
+        .map(|input| frontmatter::split(&input).0)
+        .unwrap_or_default();
+    schedule.insert(path.to_path_buf(), (modified, front_matter.clone()));
+    front_matter
+}
+
+/// The documents readers may see, so a scheduled page shows up as
+/// created when its time comes.
+fn snapshot(base_dir: &Path, drafts: bool, policy: Policy, schedule: &mut Schedule) -> Snapshot {
+    let mut documents = Vec::new();
+    index::walk(policy, base_dir, base_dir, &mut documents);
+    let present: ::std::collections::HashSet<&PathBuf> = documents.iter().map(|(p, _)| p).collect();
+    schedule.retain(|path, _| present.contains(path));
+    let now = Utc::now();
+    documents
+        .into_iter()
+        .filter_map(|(path, modified)| {
+            let rel = path.strip_prefix(base_dir).ok()?.to_path_buf();
+            if !drafts && crate::is_draft_path(&rel) {
+                return None;
+            }
+            let front_matter = front_matter(&path, modified, schedule);
+            let scheduled = front_matter.publish_at().map_or(false, |at| at > now);
+            if !drafts && scheduled {
+                return None;
+            }
+            Some((rel, (modified, front_matter)))
+        })
+        .collect()
+}
+
+fn change(kind: &'static str, rel: &Path, front_matter: &FrontMatter) -> Change {
+    Change {
+        kind,
+        path: rel.to_string_lossy().replace('\\', "/"),
+        url: index::url_for(rel),
+        front_matter: front_matter.clone(),
+    }
+}
+
+fn changes(before: &Snapshot, after: &Snapshot) -> Vec<Change> {
+    let mut found: Vec<Change> = after
+        .iter()
+        .filter_map(|(rel, (modified, front_matter))| match before.get(rel) {
+            None => Some(change("created", rel, front_matter)),
+            Some((m, _)) if m != modified => Some(change("modified", rel, front_matter)),
+            _ => None,
+        })
+        .collect();
+    found.extend(
+        before
+            .iter()
+            .filter(|(rel, _)| !after.contains_key(*rel))
+            .map(|(rel, (_, front_matter))| change("deleted", rel, front_matter)),
+    );
+    found.sort_by(|a, b| a.path.cmp(&b.path));
+    found
+}
+
+/// Polls the tree like `build --watch` does, telling subscribers what
+/// changed and bringing the index up to date with it.
+pub async fn watch(
+    base_dir: PathBuf,
+    drafts: bool,
+    policy: Policy,
+    index: index::Index,
+    events: ::std::sync::Arc<Events>,
+) {
+    let scan = |base_dir: PathBuf, mut schedule: Schedule| {
+        tokio::task::spawn_blocking(move || {
+            let current = snapshot(&base_dir, drafts, policy, &mut schedule);
+            (current, schedule)
+        })
+    };
+    let (mut known, mut schedule) = match scan(base_dir.clone(), Schedule::new()).await {
+        Ok(scanned) => scanned,
+        Err(_) => return,
+    };
+    index::scan(&base_dir, policy, &index).await;
+    loop {
+        tokio::time::delay_for(POLL_INTERVAL).await;
+        let current = match scan(base_dir.clone(), ::std::mem::take(&mut schedule)).await {
+            Ok((current, kept)) => {
+                schedule = kept;
+                current
+            }
+            Err(_) => continue,
+        };
+        let changed = changes(&known, &current);
+        if !changed.is_empty() {
+            index::scan(&base_dir, policy, &index).await;
+        }
+        for change in changed {
+            // nobody listening is fine
+            let _ = events.sender.send(change);
+        }
+        known = current;
+    }
+}
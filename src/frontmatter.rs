@@ -0,0 +1,103 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use serde::{Serialize, Serializer};
+use serde_yaml::{Mapping, Value};
+
+/// YAML metadata found between `---` fences at the top of a document.
+#[derive(Clone, Default, Debug)]
+pub struct FrontMatter(Mapping);
+
+impl FrontMatter {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.0.get(&Value::String(String::from(key)))
+    }
+
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.get(key).and_then(Value::as_str)
+    }
+
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        self.get(key).and_then(Value::as_bool)
+    }
+
+    /// A list of strings, accepting both `key: [a, b]` and `key: a`.
+    pub fn get_list(&self, key: &str) -> Vec<String> {
+        match self.get(key) {
+            Some(Value::Sequence(items)) => items
+                .iter()
+                .filter_map(Value::as_str)
+                .map(String::from)
+                .collect(),
+            Some(Value::String(s)) => vec![s.clone()],
+            _ => Vec::new(),
+        }
+    }
+
+    /// When the page goes live, from `publish_at` or else `date`.
+    pub fn publish_at(&self) -> Option<DateTime<Utc>> {
+        self.get_str("publish_at")
+            .or_else(|| self.get_str("date"))
+            .and_then(parse_date)
+    }
+
+    /// Dated in the future, kept out of sight like drafts until then.
+    pub fn is_scheduled(&self) -> bool {
+        self.publish_at().map_or(false, |at| at > Utc::now())
+    }
+
+    /// Marked `draft`, or scheduled.
+    pub fn is_draft(&self) -> bool {
+        self.get_bool("draft").unwrap_or(false) || self.is_scheduled()
+    }
+}
+
+/// RFC 3339, or `2020-01-31` and `2020-01-31 08:30` taken as UTC.
+fn parse_date(s: &str) -> Option<DateTime<Utc>> {
+    let s = s.trim();
+    if let Ok(at) = DateTime::parse_from_rfc3339(s) {
+        return Some(at.with_timezone(&Utc));
+    }
+    let naive = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M"))
+        .or_else(|_| NaiveDate::parse_from_str(s, "%Y-%m-%d").map(|d| d.and_hms(0, 0, 0)))
+        .ok()?;
+    Some(DateTime::from_utc(naive, Utc))
+}
+
+impl Serialize for FrontMatter {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+fn is_fence(line: &str) -> bool {
+    let line = line.trim_end();
+    line == "---" || line == "..."
+}
+
+/// Separates the front matter from the markdown body. Documents without
+/// a well formed block come back untouched with an empty `FrontMatter`.
+pub fn split(input: &str) -> (FrontMatter, &str) {
+    let rest = match input
+        .strip_prefix("---\n")
+        .or_else(|| input.strip_prefix("---\r\n"))
+    {
+        Some(rest) => rest,
+        None => return (FrontMatter::default(), input),
+    };
+
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if is_fence(line) {
+            let yaml = &rest[..offset];
+            let body = &rest[offset + line.len()..];
+            return match serde_yaml::from_str::<Mapping>(yaml) {
+                Ok(map) => (FrontMatter(map), body),
+                Err(_) if yaml.trim().is_empty() => (FrontMatter::default(), body),
+                Err(_) => (FrontMatter::default(), input),
+            };
+        }
+        offset += line.len();
+    }
+
+    (FrontMatter::default(), input)
+}
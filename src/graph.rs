@@ -0,0 +1,119 @@
+use crate::index::Entry;
+use crate::links;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+
+const GRAPH_CSS: &'static str = include_str!("html/graph.css");
+const GRAPH_JS: &'static str = include_str!("html/graph.js");
+
+#[derive(Serialize)]
+pub struct Node {
+    /// Where the page is served.
+    pub id: String,
+    pub path: String,
+    pub title: String,
+    pub tags: Vec<String>,
+    /// Pages it links to, and pages linking to it.
+    pub links: usize,
+    pub backlinks: usize,
+}
+
+/// `from` links to `to`, both node ids.
+#[derive(Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Edge {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Serialize)]
+pub struct Graph {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+}
+
+/// The pages of `entries` and the links between them, each counted once;
+/// links to pages not among them, or to the page itself, are left out.
+pub fn build(entries: &[Entry]) -> Graph {
+    let ids: HashMap<String, String> = entries
+        .iter()
+        .map(|e| (String::from(e.file_url().trim_end_matches('/')), e.url()))
+        .collect();
+    let mut edges = BTreeSet::new();
+    for entry in entries {
+        let from = entry.url();
+        let file_url = entry.file_url();
+        for href in entry.links.iter() {
+            let target = match links::resolve(&file_url, href) {
+                Some(target) => target,
+                None => continue,
+            };
+            match ids.get(target.trim_end_matches('/')) {
+                Some(to) if *to != from => {
+                    edges.insert(Edge {
+                        from: from.clone(),
+                        to: to.clone(),
+                    });
+                }
+                _ => {}
+            }
+        }
+    }
+    let mut out: HashMap<&str, usize> = HashMap::new();
+    let mut into: HashMap<&str, usize> = HashMap::new();
+    for edge in edges.iter() {
+        *out.entry(edge.from.as_str()).or_default() += 1;
+        *into.entry(edge.to.as_str()).or_default() += 1;
+    }
+    let nodes = entries
+        .iter()
+        .map(|entry| {
+            let id = entry.url();
+            Node {
+                path: entry.path.to_string_lossy().replace('\\', "/"),
+                title: entry.title.clone(),
+                tags: entry.tags(),
+                links: out.get(id.as_str()).cloned().unwrap_or(0),
+                backlinks: into.get(id.as_str()).cloned().unwrap_or(0),
+                id,
+            }
+        })
+        .collect();
+    Graph {
+        nodes,
+        edges: edges.into_iter().collect(),
+    }
+}
+
+fn quoted(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// The graph for Graphviz, nodes labelled with their titles.
+pub fn dot(graph: &Graph) -> String {
+    let mut out = String::from("digraph mdserve {\n");
+    for node in graph.nodes.iter() {
+        out.push_str(&format!(
+            "  {} [label={}];\n",
+            quoted(&node.id),
+            quoted(&node.title)
+        ));
+    }
+    for edge in graph.edges.iter() {
+        out.push_str(&format!(
+            "  {} -> {};\n",
+            quoted(&edge.from),
+            quoted(&edge.to)
+        ));
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// A page drawing the graph it fetches from `/_graph?format=json`.
+pub fn page() -> String {
+    format!(
+        "<h1>Links</h1>\n<input class=\"graph-filter\" type=\"search\" placeholder=\"Find a page\">\n\
+         <svg class=\"graph\"></svg>\n<style>\n{}</style>\n<script>\n{}</script>\n",
+        GRAPH_CSS, GRAPH_JS
+    )
+}
@@ -0,0 +1,202 @@
+(function () {
+    var endpoint = document.currentScript.dataset.annotations;
+    var content = document.querySelector('.content') || document.body;
+    var notes = document.createElement('div');
+    notes.className = 'margin-notes';
+    document.body.appendChild(notes);
+    var shown = [];
+
+    function textNodes() {
+        var walker = document.createTreeWalker(content, NodeFilter.SHOW_TEXT, null, false);
+        var nodes = [];
+        while (walker.nextNode()) {
+            if (!walker.currentNode.parentNode.closest('.margin-notes, script, style')) {
+                nodes.push(walker.currentNode);
+            }
+        }
+        return nodes;
+    }
+
+    // the pieces of text nodes holding the quote, where prefix and suffix match if anywhere
+    function locate(annotation) {
+        var nodes = textNodes();
+        var text = nodes.map(function (n) { return n.data; }).join('');
+        var quote = annotation.quote;
+        var found = -1;
+        for (var at = text.indexOf(quote); at >= 0; at = text.indexOf(quote, at + 1)) {
+            if (found < 0) {
+                found = at;
+            }
+            var before = text.slice(Math.max(0, at - annotation.prefix.length), at);
+            var after = text.slice(at + quote.length, at + quote.length + annotation.suffix.length);
+            if (before === annotation.prefix && after === annotation.suffix) {
+                found = at;
+                break;
+            }
+        }
+        if (found < 0) {
+            return [];
+        }
+        var pieces = [];
+        var offset = 0;
+        var end = found + quote.length;
+        nodes.forEach(function (node) {
+            var from = Math.max(found, offset);
+            var to = Math.min(end, offset + node.data.length);
+            if (from < to) {
+                pieces.push({ node: node, start: from - offset, end: to - offset });
+            }
+            offset += node.data.length;
+        });
+        return pieces;
+    }
+
+    function mark(annotation) {
+        return locate(annotation).map(function (piece) {
+            var node = piece.node.splitText(piece.start);
+            node.splitText(piece.end - piece.start);
+            var marked = document.createElement('mark');
+            marked.className = 'annotation';
+            marked.dataset.annotation = annotation.id;
+            node.parentNode.insertBefore(marked, node);
+            marked.appendChild(node);
+            return marked;
+        });
+    }
+
+    function unmark(marks) {
+        marks.forEach(function (marked) {
+            var parent = marked.parentNode;
+            while (marked.firstChild) {
+                parent.insertBefore(marked.firstChild, marked);
+            }
+            parent.removeChild(marked);
+            parent.normalize();
+        });
+    }
+
+    // notes next to their text, pushed down so none covers another
+    function layout() {
+        var bottom = 0;
+        shown.sort(function (a, b) { return a.top() - b.top(); }).forEach(function (item) {
+            var top = Math.max(item.top(), bottom);
+            item.note.style.top = top + 'px';
+            bottom = top + item.note.offsetHeight + 8;
+        });
+    }
+
+    function request(method, url, body) {
+        return fetch(url, {
+            method: method,
+            credentials: 'same-origin',
+            headers: { 'Content-Type': 'application/json' },
+            body: body ? JSON.stringify(body) : undefined
+        }).then(function (response) {
+            if (!response.ok) {
+                throw new Error(response.statusText);
+            }
+            return response.json();
+        });
+    }
+
+    function show(annotation) {
+        var marks = mark(annotation);
+        var note = document.createElement('aside');
+        note.className = 'margin-note';
+        var author = document.createElement('div');
+        author.className = 'author';
+        author.textContent = annotation.user + ', ' + annotation.created.slice(0, 10);
+        var body = document.createElement('p');
+        body.textContent = annotation.body;
+        var resolve = document.createElement('button');
+        resolve.textContent = 'Resolve';
+        note.appendChild(author);
+        note.appendChild(body);
+        note.appendChild(resolve);
+        notes.appendChild(note);
+        var item = {
+            note: note,
+            top: function () {
+                return marks.length ? marks[0].getBoundingClientRect().top + window.scrollY : 0;
+            }
+        };
+        shown.push(item);
+        resolve.addEventListener('click', function () {
+            request('POST', endpoint + '?resolve=' + encodeURIComponent(annotation.id), {}).then(function () {
+                unmark(marks);
+                notes.removeChild(note);
+                shown.splice(shown.indexOf(item), 1);
+                layout();
+            });
+        });
+        note.addEventListener('mouseenter', function () {
+            marks.forEach(function (m) { m.classList.add('active'); });
+        });
+        note.addEventListener('mouseleave', function () {
+            marks.forEach(function (m) { m.classList.remove('active'); });
+        });
+    }
+
+    // where the selection starts in the text of the page
+    function offsetOf(range) {
+        var offset = 0;
+        var nodes = textNodes();
+        for (var i = 0; i < nodes.length; i++) {
+            if (nodes[i] === range.startContainer) {
+                return offset + range.startOffset;
+            }
+            offset += nodes[i].data.length;
+        }
+        return -1;
+    }
+
+    var button = document.createElement('button');
+    button.className = 'annotate-button';
+    button.textContent = 'Comment';
+    button.style.display = 'none';
+    document.body.appendChild(button);
+    var selected = null;
+
+    document.addEventListener('mouseup', function (e) {
+        if (e.target === button) {
+            return;
+        }
+        var selection = window.getSelection();
+        var range = selection.rangeCount ? selection.getRangeAt(0) : null;
+        if (!range || range.collapsed || !content.contains(range.commonAncestorContainer)) {
+            button.style.display = 'none';
+            return;
+        }
+        selected = range.cloneRange();
+        var box = range.getBoundingClientRect();
+        button.style.top = (box.bottom + window.scrollY + 4) + 'px';
+        button.style.left = (box.left + window.scrollX) + 'px';
+        button.style.display = '';
+    });
+
+    button.addEventListener('click', function () {
+        button.style.display = 'none';
+        var body = selected && window.prompt('Comment');
+        if (!body) {
+            return;
+        }
+        var quote = selected.toString();
+        var text = textNodes().map(function (n) { return n.data; }).join('');
+        var at = offsetOf(selected);
+        request('POST', endpoint, {
+            quote: quote,
+            prefix: at > 0 ? text.slice(Math.max(0, at - 32), at) : '',
+            suffix: at >= 0 ? text.slice(at + quote.length, at + quote.length + 32) : '',
+            body: body
+        }).then(function (annotation) {
+            show(annotation);
+            layout();
+        });
+    });
+
+    window.addEventListener('resize', layout);
+    request('GET', endpoint).then(function (annotations) {
+        annotations.filter(function (a) { return !a.resolved; }).forEach(show);
+        layout();
+    });
+})();
@@ -0,0 +1,158 @@
+(function () {
+    var NS = 'http://www.w3.org/2000/svg';
+    var svg = document.querySelector('svg.graph');
+    var filter = document.querySelector('.graph-filter');
+
+    function element(name, attributes, parent) {
+        var el = document.createElementNS(NS, name);
+        Object.keys(attributes).forEach(function (key) {
+            el.setAttribute(key, attributes[key]);
+        });
+        parent.appendChild(el);
+        return el;
+    }
+
+    function draw(graph) {
+        var width = svg.clientWidth;
+        var height = svg.clientHeight;
+        var byId = {};
+        var nodes = graph.nodes.map(function (node, i) {
+            var angle = i * 2.4;
+            var radius = 10 * Math.sqrt(i + 1);
+            var n = {
+                data: node,
+                x: width / 2 + radius * Math.cos(angle),
+                y: height / 2 + radius * Math.sin(angle),
+                vx: 0,
+                vy: 0,
+                r: 4 + Math.sqrt(node.backlinks) * 2
+            };
+            byId[node.id] = n;
+            return n;
+        });
+        var edges = graph.edges
+            .filter(function (e) { return byId[e.from] && byId[e.to]; })
+            .map(function (e) { return { from: byId[e.from], to: byId[e.to] }; });
+
+        var view = element('g', {}, svg);
+        edges.forEach(function (e) {
+            e.line = element('line', {}, view);
+        });
+        nodes.forEach(function (n) {
+            n.group = element('g', { class: 'node' }, view);
+            var circle = element('circle', { r: n.r }, n.group);
+            element('title', {}, circle).textContent = n.data.title + ' (' + n.data.id + ')';
+            element('text', { x: n.r + 3, y: 4 }, n.group).textContent = n.data.title;
+            circle.addEventListener('click', function () {
+                if (!dragged) {
+                    window.location = n.data.id;
+                }
+            });
+            circle.addEventListener('mousedown', function (ev) {
+                ev.stopPropagation();
+                held = n;
+                dragged = false;
+            });
+        });
+
+        function place() {
+            edges.forEach(function (e) {
+                e.line.setAttribute('x1', e.from.x);
+                e.line.setAttribute('y1', e.from.y);
+                e.line.setAttribute('x2', e.to.x);
+                e.line.setAttribute('y2', e.to.y);
+            });
+            nodes.forEach(function (n) {
+                n.group.setAttribute('transform', 'translate(' + n.x + ',' + n.y + ')');
+            });
+        }
+
+        // a plain force layout: nodes push each other away, links pull
+        // theirs together and everything drifts to the middle
+        var heat = 1;
+        function step() {
+            for (var i = 0; i < nodes.length; i++) {
+                for (var j = i + 1; j < nodes.length; j++) {
+                    var a = nodes[i], b = nodes[j];
+                    var dx = a.x - b.x, dy = a.y - b.y;
+                    var d2 = Math.max(dx * dx + dy * dy, 1);
+                    var push = 400 / d2;
+                    a.vx += dx * push; a.vy += dy * push;
+                    b.vx -= dx * push; b.vy -= dy * push;
+                }
+            }
+            edges.forEach(function (e) {
+                var dx = e.to.x - e.from.x, dy = e.to.y - e.from.y;
+                e.from.vx += dx * 0.01; e.from.vy += dy * 0.01;
+                e.to.vx -= dx * 0.01; e.to.vy -= dy * 0.01;
+            });
+            nodes.forEach(function (n) {
+                n.vx += (width / 2 - n.x) * 0.002;
+                n.vy += (height / 2 - n.y) * 0.002;
+                if (n !== held) {
+                    n.x += n.vx * heat;
+                    n.y += n.vy * heat;
+                }
+                n.vx *= 0.6;
+                n.vy *= 0.6;
+            });
+            place();
+            heat *= 0.99;
+            if (heat > 0.02) {
+                window.requestAnimationFrame(step);
+            }
+        }
+
+        var held = null, dragged = false, panning = null;
+        var pan = { x: 0, y: 0, scale: 1 };
+        function transform() {
+            view.setAttribute('transform', 'translate(' + pan.x + ',' + pan.y + ') scale(' + pan.scale + ')');
+        }
+        svg.addEventListener('mousedown', function (ev) {
+            panning = { x: ev.clientX - pan.x, y: ev.clientY - pan.y };
+        });
+        window.addEventListener('mousemove', function (ev) {
+            if (held) {
+                var box = svg.getBoundingClientRect();
+                held.x = (ev.clientX - box.left - pan.x) / pan.scale;
+                held.y = (ev.clientY - box.top - pan.y) / pan.scale;
+                dragged = true;
+                if (heat <= 0.02) {
+                    heat = 0.3;
+                    window.requestAnimationFrame(step);
+                }
+                place();
+            } else if (panning) {
+                pan.x = ev.clientX - panning.x;
+                pan.y = ev.clientY - panning.y;
+                transform();
+            }
+        });
+        window.addEventListener('mouseup', function () {
+            held = null;
+            panning = null;
+        });
+        svg.addEventListener('wheel', function (ev) {
+            ev.preventDefault();
+            pan.scale = Math.min(4, Math.max(0.2, pan.scale * (ev.deltaY < 0 ? 1.1 : 0.9)));
+            transform();
+        });
+
+        filter.addEventListener('input', function () {
+            var q = filter.value.trim().toLowerCase();
+            svg.classList.toggle('filtering', q.length > 0);
+            nodes.forEach(function (n) {
+                var found = q.length > 0 &&
+                    (n.data.title.toLowerCase().indexOf(q) >= 0 || n.data.id.toLowerCase().indexOf(q) >= 0);
+                n.group.classList.toggle('found', found);
+            });
+        });
+
+        place();
+        window.requestAnimationFrame(step);
+    }
+
+    fetch('/_graph?format=json', { credentials: 'same-origin' })
+        .then(function (response) { return response.json(); })
+        .then(draw);
+})();
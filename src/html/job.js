@@ -0,0 +1,20 @@
+(function () {
+    var status = document.querySelector('.job');
+
+    function poll() {
+        fetch(status.dataset.job)
+            .then(function (response) { return response.json(); })
+            .then(function (job) {
+                if (job.state === 'done') {
+                    status.textContent = 'Done.';
+                    window.location.href = job.download;
+                } else if (job.state === 'failed') {
+                    status.textContent = 'The export failed: ' + job.error;
+                } else {
+                    setTimeout(poll, 1000);
+                }
+            });
+    }
+
+    poll();
+})();
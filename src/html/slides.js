@@ -0,0 +1,48 @@
+(function () {
+    var slides = document.querySelectorAll('.slide');
+    var progress = document.querySelector('.progress');
+    var current = 0;
+
+    function show(index) {
+        current = Math.max(0, Math.min(slides.length - 1, index));
+        for (var i = 0; i < slides.length; i++) {
+            slides[i].classList.toggle('current', i === current);
+        }
+        progress.textContent = (current + 1) + ' / ' + slides.length;
+        history.replaceState(null, '', '#' + (current + 1));
+    }
+
+    document.addEventListener('keydown', function (e) {
+        switch (e.key) {
+            case 'ArrowRight':
+            case 'ArrowDown':
+            case 'PageDown':
+            case ' ':
+                show(current + 1);
+                break;
+            case 'ArrowLeft':
+            case 'ArrowUp':
+            case 'PageUp':
+                show(current - 1);
+                break;
+            case 'Home':
+                show(0);
+                break;
+            case 'End':
+                show(slides.length - 1);
+                break;
+            case 'f':
+                if (document.fullscreenElement) {
+                    document.exitFullscreen();
+                } else {
+                    document.documentElement.requestFullscreen();
+                }
+                break;
+            default:
+                return;
+        }
+        e.preventDefault();
+    });
+
+    show(parseInt(location.hash.slice(1), 10) - 1 || 0);
+})();
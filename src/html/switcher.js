@@ -0,0 +1,138 @@
+(function () {
+    var documents = null;
+    var overlay = null;
+    var input = null;
+    var list = null;
+    var matches = [];
+    var selected = 0;
+
+    function flatten(dir, out) {
+        dir.documents.forEach(function (doc) {
+            out.push({ title: doc.title, url: doc.url, path: doc.path });
+        });
+        dir.dirs.forEach(function (sub) {
+            flatten(sub, out);
+        });
+        return out;
+    }
+
+    function load() {
+        if (documents) {
+            return Promise.resolve(documents);
+        }
+        return fetch('/_api/tree')
+            .then(function (response) { return response.json(); })
+            .then(function (tree) {
+                documents = flatten(tree, []);
+                return documents;
+            });
+    }
+
+    // every character of the query in order, runs and word starts scoring higher
+    function score(query, text) {
+        text = text.toLowerCase();
+        var total = 0;
+        var at = 0;
+        var previous = -2;
+        for (var i = 0; i < query.length; i++) {
+            var found = text.indexOf(query[i], at);
+            if (found < 0) {
+                return -1;
+            }
+            total += found === previous + 1 ? 3 : 1;
+            if (found === 0 || /[\s\/_-]/.test(text[found - 1])) {
+                total += 2;
+            }
+            previous = found;
+            at = found + 1;
+        }
+        return total - text.length / 100;
+    }
+
+    function render() {
+        list.innerHTML = '';
+        matches.slice(0, 20).forEach(function (doc, i) {
+            var item = document.createElement('li');
+            var link = document.createElement('a');
+            link.href = doc.url;
+            link.textContent = doc.title;
+            var path = document.createElement('span');
+            path.className = 'path';
+            path.textContent = doc.path;
+            item.appendChild(link);
+            item.appendChild(path);
+            if (i === selected) {
+                item.className = 'selected';
+            }
+            list.appendChild(item);
+        });
+    }
+
+    function update() {
+        var query = input.value.trim().toLowerCase();
+        matches = documents
+            .map(function (doc) {
+                return { doc: doc, score: Math.max(score(query, doc.title), score(query, doc.path) - 1) };
+            })
+            .filter(function (m) { return m.score >= 0; })
+            .sort(function (a, b) { return b.score - a.score; })
+            .map(function (m) { return m.doc; });
+        selected = 0;
+        render();
+    }
+
+    function close() {
+        if (overlay) {
+            overlay.remove();
+            overlay = null;
+        }
+    }
+
+    function open() {
+        if (overlay) {
+            return;
+        }
+        overlay = document.createElement('div');
+        overlay.className = 'quick-switcher';
+        overlay.setAttribute('role', 'dialog');
+        input = document.createElement('input');
+        input.type = 'search';
+        input.placeholder = 'Jump to…';
+        input.setAttribute('aria-label', 'Jump to a document');
+        list = document.createElement('ul');
+        overlay.appendChild(input);
+        overlay.appendChild(list);
+        overlay.addEventListener('click', function (e) {
+            if (e.target === overlay) {
+                close();
+            }
+        });
+        input.addEventListener('input', update);
+        input.addEventListener('keydown', function (e) {
+            if (e.key === 'ArrowDown') {
+                selected = Math.min(selected + 1, Math.min(matches.length, 20) - 1);
+                render();
+                e.preventDefault();
+            } else if (e.key === 'ArrowUp') {
+                selected = Math.max(selected - 1, 0);
+                render();
+                e.preventDefault();
+            } else if (e.key === 'Enter' && matches[selected]) {
+                window.location.href = matches[selected].url;
+            } else if (e.key === 'Escape') {
+                close();
+            }
+        });
+        document.body.appendChild(overlay);
+        input.focus();
+        load().then(update);
+    }
+
+    document.addEventListener('keydown', function (e) {
+        var typing = /^(input|textarea|select)$/i.test(e.target.tagName) || e.target.isContentEditable;
+        if ((e.key === 'k' && (e.ctrlKey || e.metaKey)) || (e.key === '/' && !typing)) {
+            e.preventDefault();
+            open();
+        }
+    });
+})();
@@ -0,0 +1,247 @@
+use crate::frontmatter::{self, FrontMatter};
+use crate::symlinks::{self, Policy};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tokio::sync::Mutex;
+
+/// What we know of a document without rendering it.
+#[derive(Clone)]
+pub struct Entry {
+    /// Path relative to the base directory.
+    pub path: PathBuf,
+    pub modified: SystemTime,
+    pub title: String,
+    pub front_matter: FrontMatter,
+    /// Link destinations found in the body, as written.
+    pub links: Vec<String>,
+    pub excerpt: String,
+    /// The markdown body, lowercased for searching.
+    pub text: String,
+}
+
+/// The URL serving the document at `path`, relative to the base directory.
+pub fn url_for(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let path = path.strip_suffix(".md").unwrap_or(&path);
+    match path.strip_suffix("index") {
+        Some(dir) if dir.is_empty() || dir.ends_with('/') => format!("/{}", dir),
+        _ => format!("/{}", path),
+    }
+}
+
+fn absolute(url: &str) -> String {
+    if url.starts_with('/') {
+        String::from(url)
+    } else {
+        format!("/{}", url)
+    }
+}
+
+impl Entry {
+    /// Where the page is served, its `permalink:` when it has one.
+    pub fn url(&self) -> String {
+        self.front_matter
+            .get_str("permalink")
+            .map(absolute)
+            .unwrap_or_else(|| self.file_url())
+    }
+
+    /// The URL following from the file's path, which its links are relative to.
+    pub fn file_url(&self) -> String {
+        url_for(&self.path)
+    }
+
+    /// Former URLs of the page, from `aliases:`.
+    pub fn aliases(&self) -> Vec<String> {
+        self.front_matter
+            .get_list("aliases")
+            .iter()
+            .map(|a| absolute(a))
+            .collect()
+    }
+
+    pub fn tags(&self) -> Vec<String> {
+        self.front_matter.get_list("tags")
+    }
+
+    pub fn is_draft(&self) -> bool {
+        crate::is_draft_path(&self.path) || self.front_matter.is_draft()
+    }
+}
+
+pub type Index = ::std::sync::Arc<Mutex<HashMap<PathBuf, Entry>>>;
+
+/// What to do with a request for `url` because of some page's front matter.
+pub enum Claim {
+    /// Send the client to the page's URL.
+    Moved(String),
+    /// Serve the page found at this file URL.
+    Serve(String),
+}
+
+/// Looks `url` up in the aliases and permalinks known from the last scan,
+/// scanning first if nothing is known yet.
+pub async fn claim(base_dir: &Path, policy: Policy, index: &Index, url: &str) -> Option<Claim> {
+    if index.lock().await.is_empty() {
+        scan(base_dir, policy, index).await;
+    }
+    let index = index.lock().await;
+    index.values().find_map(|entry| {
+        let permalink = entry.url();
+        let file_url = entry.file_url();
+        if entry.aliases().iter().any(|a| a == url) {
+            Some(Claim::Moved(permalink))
+        } else if permalink != file_url && permalink == url {
+            Some(Claim::Serve(file_url))
+        } else if permalink != file_url && file_url == url {
+            Some(Claim::Moved(permalink))
+        } else {
+            None
+        }
+    })
+}
+
+const EXCERPT_LENGTH: usize = 200;
+
+/// The opening paragraph of a markdown body as plain text, cut short.
+pub fn first_paragraph(body: &str) -> String {
+    use comrak::nodes::NodeValue;
+    let arena = comrak::Arena::new();
+    let root = comrak::parse_document(&arena, body, &crate::render::DEFAULT.comrak);
+    let text = root
+        .descendants()
+        .find(|n| matches!(n.data.borrow().value, NodeValue::Paragraph))
+        .map(crate::toc::text_of)
+        .unwrap_or_default();
+    match text.char_indices().nth(EXCERPT_LENGTH) {
+        Some((i, _)) => format!("{}…", text[..i].trim_end()),
+        None => text,
+    }
+}
+
+/// The first ATX heading of a markdown body.
+pub fn first_heading(body: &str) -> Option<String> {
+    body.lines()
+        .map(str::trim_start)
+        .find(|l| l.starts_with('#'))
+        .map(|l| {
+            l.trim_start_matches('#')
+                .trim()
+                .trim_end_matches('#')
+                .trim()
+        })
+        .filter(|l| !l.is_empty())
+        .map(String::from)
+}
+
+fn read_entry(base_dir: &Path, path: &Path, modified: SystemTime) -> Option<Entry> {
+    let input = crate::encoding::read_to_string(path).ok()?;
+    let (front_matter, body) = frontmatter::split(&input);
+    let rel = path.strip_prefix(base_dir).ok()?.to_path_buf();
+    let title = front_matter
+        .get_str("title")
+        .map(String::from)
+        .or_else(|| first_heading(body))
+        .unwrap_or_else(|| {
+            rel.file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        });
+    Some(Entry {
+        path: rel,
+        modified,
+        title,
+        links: crate::links::extract(body),
+        excerpt: front_matter
+            .get_str("description")
+            .map(String::from)
+            .unwrap_or_else(|| first_paragraph(body)),
+        text: body.to_lowercase(),
+        front_matter,
+    })
+}
+
+/// The documents below `dir` with their modification times, leaving out
+/// hidden directories, section partials and the links `policy` doesn't
+/// follow from `base_dir`.
+pub fn walk(policy: Policy, base_dir: &Path, dir: &Path, found: &mut Vec<(PathBuf, SystemTime)>) {
+    let entries = match ::std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        let hidden = name.starts_with('.');
+        let partial = name == "_header.md" || name == "_footer.md";
+        match symlinks::metadata(policy, base_dir, &entry) {
+            Some(meta) if meta.is_dir() && !hidden => walk(policy, base_dir, &path, found),
+            Some(meta)
+                if meta.is_file()
+                    && !partial
+                    && path.extension().map(|e| e == "md").unwrap_or(false) =>
+            {
+                if let Ok(modified) = meta.modified() {
+                    found.push((path, modified));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Brings the index in line with the tree, re-reading only what changed.
+/// Entries come back sorted by path.
+pub async fn scan(base_dir: &Path, policy: Policy, index: &Index) -> Vec<Entry> {
+    let mut found = Vec::new();
+    walk(policy, base_dir, base_dir, &mut found);
+
+    let found: HashMap<PathBuf, SystemTime> = found.into_iter().collect();
+
+    let mut index = index.lock().await;
+    index.retain(|path, _| found.contains_key(path));
+    for (path, modified) in found {
+        let fresh = index
+            .get(&path)
+            .map(|e| e.modified == modified)
+            .unwrap_or(false);
+        if !fresh {
+            match read_entry(base_dir, &path, modified) {
+                Some(entry) => {
+                    index.insert(path, entry);
+                }
+                None => {
+                    index.remove(&path);
+                }
+            }
+        }
+    }
+
+    let mut entries: Vec<Entry> = index.values().cloned().collect();
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    entries
+}
+
+/// What the index knows, sorted by path, for a server that leaves keeping
+/// it up to date to `events::watch`; scanned first if nothing is known yet.
+pub async fn entries(base_dir: &Path, policy: Policy, index: &Index) -> Vec<Entry> {
+    {
+        let known = index.lock().await;
+        if !known.is_empty() {
+            let mut entries: Vec<Entry> = known.values().cloned().collect();
+            entries.sort_by(|a, b| a.path.cmp(&b.path));
+            return entries;
+        }
+    }
+    scan(base_dir, policy, index).await
+}
+
+/// Leaves drafts out unless they're being served.
+pub fn published(entries: Vec<Entry>, drafts: bool) -> Vec<Entry> {
+    entries
+        .into_iter()
+        .filter(|e| drafts || !e.is_draft())
+        .collect()
+}
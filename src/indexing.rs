@@ -0,0 +1,58 @@
+use crate::frontmatter::FrontMatter;
+use crate::index::Entry;
+use crate::listing::escape;
+use chrono::{DateTime, Utc};
+
+/// The canonical link and robots tag a page's front matter asks for.
+pub fn meta(front_matter: &FrontMatter) -> String {
+    let mut tags = String::new();
+    if let Some(canonical) = front_matter.get_str("canonical") {
+        tags.push_str(&format!(
+            "    <link rel=\"canonical\" href=\"{}\" />\n",
+            escape(canonical)
+        ));
+    }
+    if front_matter.get_bool("noindex").unwrap_or(false) {
+        tags.push_str("    <meta name=\"robots\" content=\"noindex\" />\n");
+    }
+    tags
+}
+
+/// `head` with `meta` at the end of its `<head>`.
+pub fn with_meta(head: &str, meta: &str) -> String {
+    match head.find("</head>") {
+        Some(end) if !meta.is_empty() => [&head[..end], meta, &head[end..]].join(""),
+        _ => String::from(head),
+    }
+}
+
+/// Whether search engines get pointed to the page: not `noindex`, and
+/// not a copy of one whose canonical URL is elsewhere.
+pub fn listed(entry: &Entry, origin: &str) -> bool {
+    let url = entry.url();
+    !entry.front_matter.get_bool("noindex").unwrap_or(false)
+        && entry
+            .front_matter
+            .get_str("canonical")
+            .map_or(true, |c| c == url || c == format!("{}{}", origin, url))
+}
+
+pub fn sitemap(origin: &str, entries: &[Entry]) -> String {
+    let urls: Vec<String> = entries
+        .iter()
+        .filter(|entry| listed(entry, origin))
+        .map(|entry| {
+            format!(
+                "  <url>\n    <loc>{}{}</loc>\n    <lastmod>{}</lastmod>\n  </url>",
+                escape(origin),
+                escape(&entry.url()),
+                DateTime::<Utc>::from(entry.modified).format("%Y-%m-%d")
+            )
+        })
+        .collect();
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n{}\n</urlset>\n",
+        urls.join("\n")
+    )
+}
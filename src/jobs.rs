@@ -0,0 +1,196 @@
+use crate::listing::escape;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+const JOB_JS: &'static str = include_str!("html/job.js");
+
+/// Exports running at once, the others wait their turn.
+const WORKERS: usize = 2;
+/// How long a finished export stays around to be downloaded.
+const KEEP: Duration = Duration::from_secs(15 * 60);
+/// Jobs waiting or running for one owner, and for everyone together;
+/// finished ones don't count.
+const PER_OWNER: usize = 4;
+const TOTAL: usize = 32;
+
+/// A file an export made.
+pub struct Output {
+    pub bytes: Vec<u8>,
+    pub mime: &'static str,
+    pub filename: String,
+}
+
+enum State {
+    Queued,
+    Running,
+    Done(Arc<Output>),
+    Failed(String),
+}
+
+struct Job {
+    /// Who asked, the only one getting to see it.
+    owner: Option<String>,
+    state: State,
+    finished: Option<Instant>,
+}
+
+/// Why a job wasn't queued.
+#[derive(Debug)]
+pub enum Busy {
+    /// The owner has `PER_OWNER` jobs already.
+    Owner,
+    /// There are `TOTAL` jobs already.
+    Everyone,
+}
+
+/// What `/_jobs/<id>` answers.
+#[derive(Serialize)]
+pub struct Status {
+    pub id: String,
+    /// `queued`, `running`, `done` or `failed`.
+    pub state: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub download: Option<String>,
+}
+
+/// Exports running away from the request that asked for them.
+pub struct Jobs {
+    /// Keeps ids from being guessed.
+    secret: u64,
+    next: AtomicU64,
+    workers: tokio::sync::Semaphore,
+    jobs: Mutex<HashMap<String, Job>>,
+}
+
+impl Default for Jobs {
+    fn default() -> Jobs {
+        Jobs {
+            // std seeds it at random for every map
+            secret: ::std::collections::hash_map::RandomState::new()
+                .build_hasher()
+                .finish(),
+            next: AtomicU64::new(0),
+            workers: tokio::sync::Semaphore::new(WORKERS),
+            jobs: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+pub fn url(id: &str) -> String {
+    format!("/_jobs/{}", id)
+}
+
+impl Jobs {
+    fn new_id(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.input(self.secret.to_le_bytes());
+        hasher.input(self.next.fetch_add(1, Ordering::Relaxed).to_le_bytes());
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        hasher.input(now.as_nanos().to_le_bytes());
+        hasher.result()[..12]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    fn set(&self, id: &str, state: State) {
+        let mut jobs = self.jobs.lock().expect("jobs lock poisoned");
+        if let Some(job) = jobs.get_mut(id) {
+            if let State::Done(_) | State::Failed(_) = state {
+                job.finished = Some(Instant::now());
+            }
+            job.state = state;
+        }
+    }
+
+    /// Queues `work` for `owner`, giving back the job's id, unless the
+    /// owner or everyone together has too many jobs already.
+    pub fn submit<F>(self: &Arc<Self>, owner: Option<String>, work: F) -> Result<String, Busy>
+    where
+        F: FnOnce() -> Result<Output, String> + Send + 'static,
+    {
+        let id = self.new_id();
+        {
+            let mut jobs = self.jobs.lock().expect("jobs lock poisoned");
+            jobs.retain(|_, job| job.finished.map_or(true, |at| at.elapsed() < KEEP));
+            let pending: Vec<&Job> = jobs.values().filter(|job| job.finished.is_none()).collect();
+            if pending.iter().filter(|job| job.owner == owner).count() >= PER_OWNER {
+                return Err(Busy::Owner);
+            }
+            if pending.len() >= TOTAL {
+                return Err(Busy::Everyone);
+            }
+            jobs.insert(
+                id.clone(),
+                Job {
+                    owner,
+                    state: State::Queued,
+                    finished: None,
+                },
+            );
+        }
+        let queue = self.clone();
+        let job = id.clone();
+        tokio::spawn(async move {
+            let _permit = queue.workers.acquire().await;
+            queue.set(&job, State::Running);
+            let state = match tokio::task::spawn_blocking(work).await {
+                Ok(Ok(output)) => State::Done(Arc::new(output)),
+                Ok(Err(err)) => State::Failed(err),
+                Err(_) => State::Failed(String::from("the export stopped unexpectedly")),
+            };
+            queue.set(&job, state);
+        });
+        Ok(id)
+    }
+
+    /// Where the job stands, for its owner only.
+    pub fn status(&self, id: &str, owner: Option<&str>) -> Option<Status> {
+        let jobs = self.jobs.lock().expect("jobs lock poisoned");
+        let job = jobs.get(id).filter(|job| job.owner.as_deref() == owner)?;
+        let (state, error, download) = match &job.state {
+            State::Queued => ("queued", None, None),
+            State::Running => ("running", None, None),
+            State::Done(_) => ("done", None, Some(format!("{}?download", url(id)))),
+            State::Failed(err) => ("failed", Some(err.clone()), None),
+        };
+        Some(Status {
+            id: String::from(id),
+            state,
+            error,
+            download,
+        })
+    }
+
+    /// The file a finished job made.
+    pub fn output(&self, id: &str, owner: Option<&str>) -> Option<Arc<Output>> {
+        let jobs = self.jobs.lock().expect("jobs lock poisoned");
+        match jobs.get(id).filter(|job| job.owner.as_deref() == owner) {
+            Some(Job {
+                state: State::Done(output),
+                ..
+            }) => Some(output.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// A page waiting for the job, then downloading what it made.
+pub fn page(id: &str) -> String {
+    format!(
+        "<h1>Exporting</h1>\n<p class=\"job\" data-job=\"{url}\">Preparing the file, \
+         it downloads once ready. <a href=\"{url}\">Status</a></p>\n\
+         <script>\n{script}</script>\n",
+        url = escape(&url(id)),
+        script = JOB_JS
+    )
+}
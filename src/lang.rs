@@ -0,0 +1,155 @@
+use std::path::{Path, PathBuf};
+
+/// A document resolved after language negotiation.
+pub struct Negotiated {
+    pub path: PathBuf,
+    pub lang: String,
+    pub available: Vec<String>,
+}
+
+fn is_lang_tag(s: &str) -> bool {
+    let mut parts = s.split('-');
+    match parts.next() {
+        Some(primary) if (2..=3).contains(&primary.len()) => {
+            primary.chars().all(|c| c.is_ascii_alphabetic())
+                && parts.all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_alphanumeric()))
+        }
+        _ => false,
+    }
+}
+
+/// Splits `page.fr.md` into (`page`, `Some("fr")`) and `page.md` into (`page`, `None`).
+pub fn split_variant(path: &Path) -> Option<(String, Option<String>)> {
+    let stem = path.file_stem()?.to_str()?;
+    match stem.rfind('.') {
+        Some(i) if is_lang_tag(&stem[i + 1..]) => {
+            Some((String::from(&stem[..i]), Some(stem[i + 1..].to_lowercase())))
+        }
+        _ => Some((String::from(stem), None)),
+    }
+}
+
+fn variants(base: &Path) -> Vec<String> {
+    let (stem, dir) = match (split_variant(base), base.parent()) {
+        (Some((stem, None)), Some(dir)) => (stem, dir),
+        _ => return Vec::new(),
+    };
+    let entries = match ::std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut langs: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().map(|ext| ext == "md").unwrap_or(false))
+        .filter_map(|p| split_variant(&p))
+        .filter(|(s, _)| s == &stem)
+        .filter_map(|(_, lang)| lang)
+        .collect();
+    langs.sort();
+    langs
+}
+
+fn variant_path(base: &Path, lang: &str) -> PathBuf {
+    let stem = base
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default();
+    base.with_file_name(format!("{}.{}.md", stem, lang))
+}
+
+/// Parses an `Accept-Language` header into tags ordered by preference.
+fn parse_accept_language(header: &str) -> Vec<String> {
+    let mut tags: Vec<(String, f32)> = header
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.trim().split(';');
+            let tag = parts.next()?.trim().to_lowercase();
+            let q = parts
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .filter_map(|q| q.parse::<f32>().ok())
+                .next()
+                .unwrap_or(1.0);
+            if tag.is_empty() || q <= 0.0 {
+                None
+            } else {
+                Some((tag, q))
+            }
+        })
+        .collect();
+    tags.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(::std::cmp::Ordering::Equal));
+    tags.into_iter().map(|(tag, _)| tag).collect()
+}
+
+fn matches(tag: &str, lang: &str) -> bool {
+    tag == lang || tag.split('-').next() == lang.split('-').next()
+}
+
+/// Picks the variant of `base` to serve. An explicit `?lang=` wins over
+/// `Accept-Language`; `default_lang` designates the base file itself.
+pub fn negotiate(
+    base: &Path,
+    requested: Option<&str>,
+    accept: Option<&str>,
+    default_lang: &str,
+) -> Negotiated {
+    if let Some((_, Some(lang))) = split_variant(base) {
+        return Negotiated {
+            path: base.to_path_buf(),
+            lang,
+            available: Vec::new(),
+        };
+    }
+
+    let available = variants(base);
+    let mut choices: Vec<String> = available.clone();
+    if !choices.iter().any(|l| l == default_lang) {
+        choices.push(String::from(default_lang));
+        choices.sort();
+    }
+
+    let wanted = match requested {
+        Some(lang) => vec![lang.to_lowercase()],
+        None => accept.map(parse_accept_language).unwrap_or_default(),
+    };
+
+    let lang = wanted
+        .iter()
+        .find_map(|tag| {
+            choices
+                .iter()
+                .find(|l| *l == tag)
+                .or_else(|| choices.iter().find(|l| matches(tag, l)))
+        })
+        .cloned()
+        .unwrap_or_else(|| String::from(default_lang));
+
+    let path = if available.contains(&lang) {
+        variant_path(base, &lang)
+    } else {
+        base.to_path_buf()
+    };
+
+    Negotiated {
+        path,
+        lang,
+        available: choices,
+    }
+}
+
+pub fn switcher(current: &str, available: &[String]) -> String {
+    if available.len() < 2 {
+        return String::new();
+    }
+    let links: Vec<String> = available
+        .iter()
+        .map(|lang| {
+            if lang == current {
+                format!("<span class=\"current\">{}</span>", lang)
+            } else {
+                format!("<a href=\"?lang={0}\" hreflang=\"{0}\">{0}</a>", lang)
+            }
+        })
+        .collect();
+    format!("<nav class=\"lang-switcher\">{}</nav>\n", links.join(" · "))
+}
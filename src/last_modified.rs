@@ -0,0 +1,178 @@
+use crate::config;
+use crate::listing::escape;
+use chrono::{DateTime, Datelike, Local};
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+struct Locale {
+    label: &'static str,
+    months: [&'static str; 12],
+    days: [&'static str; 7],
+}
+
+const EN: Locale = Locale {
+    label: "Last modified",
+    months: [
+        "January",
+        "February",
+        "March",
+        "April",
+        "May",
+        "June",
+        "July",
+        "August",
+        "September",
+        "October",
+        "November",
+        "December",
+    ],
+    days: [
+        "Monday",
+        "Tuesday",
+        "Wednesday",
+        "Thursday",
+        "Friday",
+        "Saturday",
+        "Sunday",
+    ],
+};
+
+const FR: Locale = Locale {
+    label: "Dernière modification",
+    months: [
+        "janvier",
+        "février",
+        "mars",
+        "avril",
+        "mai",
+        "juin",
+        "juillet",
+        "août",
+        "septembre",
+        "octobre",
+        "novembre",
+        "décembre",
+    ],
+    days: [
+        "lundi", "mardi", "mercredi", "jeudi", "vendredi", "samedi", "dimanche",
+    ],
+};
+
+const NL: Locale = Locale {
+    label: "Laatst gewijzigd",
+    months: [
+        "januari",
+        "februari",
+        "maart",
+        "april",
+        "mei",
+        "juni",
+        "juli",
+        "augustus",
+        "september",
+        "oktober",
+        "november",
+        "december",
+    ],
+    days: [
+        "maandag",
+        "dinsdag",
+        "woensdag",
+        "donderdag",
+        "vrijdag",
+        "zaterdag",
+        "zondag",
+    ],
+};
+
+const DE: Locale = Locale {
+    label: "Zuletzt geändert",
+    months: [
+        "Januar",
+        "Februar",
+        "März",
+        "April",
+        "Mai",
+        "Juni",
+        "Juli",
+        "August",
+        "September",
+        "Oktober",
+        "November",
+        "Dezember",
+    ],
+    days: [
+        "Montag",
+        "Dienstag",
+        "Mittwoch",
+        "Donnerstag",
+        "Freitag",
+        "Samstag",
+        "Sonntag",
+    ],
+};
+
+/// `fr-BE` gets French, anything unknown English.
+fn locale(tag: Option<&str>) -> &'static Locale {
+    let lang = tag
+        .and_then(|t| t.split(|c| c == '-' || c == '_').next())
+        .map(str::to_lowercase);
+    match lang.as_deref() {
+        Some("fr") => &FR,
+        Some("nl") => &NL,
+        Some("de") => &DE,
+        _ => &EN,
+    }
+}
+
+/// When git last committed the file, if it's tracked.
+pub fn committed(path: &Path) -> Option<SystemTime> {
+    let output = Command::new("git")
+        .arg("log")
+        .arg("-1")
+        .arg("--format=%ct")
+        .arg("--")
+        .arg(path.file_name()?)
+        .current_dir(path.parent()?)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let seconds: u64 = String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse()
+        .ok()?;
+    Some(UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+/// `format` with chrono's specifiers, month and day names in the locale.
+fn format(when: SystemTime, format: &str, locale: &Locale) -> String {
+    let date = DateTime::<Local>::from(when);
+    let month = locale.months[date.month0() as usize];
+    let day = locale.days[date.weekday().num_days_from_monday() as usize];
+    let short = |name: &str| name.chars().take(3).collect::<String>();
+    let localized = format
+        .replace("%B", month)
+        .replace("%b", &short(month))
+        .replace("%A", day)
+        .replace("%a", &short(day));
+    date.format(&localized).to_string()
+}
+
+/// The footer telling when the page last changed, empty when switched off.
+/// Unless the config sets a locale, that of the page's language.
+pub fn footer(config: &config::LastModified, when: SystemTime, lang: &str) -> String {
+    if config.source.as_deref() == Some("off") {
+        return String::new();
+    }
+    let locale = locale(config.locale.as_deref().or(Some(lang)));
+    let date = format(when, config.format.as_deref().unwrap_or("%Y-%m-%d"), locale);
+    format!(
+        "<footer class=\"last-modified\">{}: <time datetime=\"{}\">{}</time></footer>\n",
+        escape(config.label.as_deref().unwrap_or(locale.label)),
+        DateTime::<Local>::from(when).to_rfc3339(),
+        escape(&date)
+    )
+}
@@ -0,0 +1,122 @@
+use crate::index::Entry;
+use comrak::nodes::{AstNode, NodeValue};
+use comrak::{parse_document, Arena};
+use serde::Serialize;
+
+/// Every link destination in a markdown body, as written.
+pub fn extract(body: &str) -> Vec<String> {
+    let arena = Arena::new();
+    let root = parse_document(&arena, body, &crate::render::DEFAULT.comrak);
+    root.descendants()
+        .filter_map(|node| match node.data.borrow().value {
+            NodeValue::Link(ref link) => Some(String::from_utf8_lossy(&link.url).into_owned()),
+            _ => None,
+        })
+        .collect()
+}
+
+pub fn is_external(href: &str) -> bool {
+    href.contains("://") || href.starts_with("mailto:") || href.starts_with("//")
+}
+
+/// An image or file a page refers to.
+#[derive(Clone, Serialize)]
+pub struct Attachment {
+    /// `image` or `file`.
+    pub kind: &'static str,
+    /// As written in the page.
+    pub url: String,
+}
+
+/// Images, and links to what isn't a page, inside the site; each once.
+pub fn attachments<'a>(root: &'a AstNode<'a>) -> Vec<Attachment> {
+    let mut found: Vec<Attachment> = Vec::new();
+    for node in root.descendants() {
+        let (kind, url) = match node.data.borrow().value {
+            NodeValue::Image(ref link) => {
+                ("image", String::from_utf8_lossy(&link.url).into_owned())
+            }
+            NodeValue::Link(ref link) => ("file", String::from_utf8_lossy(&link.url).into_owned()),
+            _ => continue,
+        };
+        let target = url.split(|c| c == '#' || c == '?').next().unwrap_or("");
+        let file = ::std::path::Path::new(target)
+            .extension()
+            .map(|e| e != "md")
+            .unwrap_or(false);
+        let local = !target.is_empty() && !is_external(&url) && !url.starts_with("data:");
+        if local && (kind == "image" || file) && !found.iter().any(|a| a.url == url) {
+            found.push(Attachment { kind, url });
+        }
+    }
+    found
+}
+
+/// Turns a link found on the page at `from` into the URL of the document
+/// it points to, if it points inside the site at all.
+pub fn resolve(from: &str, href: &str) -> Option<String> {
+    if is_external(href) {
+        return None;
+    }
+    let target = href.split(|c| c == '#' || c == '?').next()?;
+    if target.is_empty() {
+        return None;
+    }
+
+    let joined = if target.starts_with('/') {
+        String::from(target)
+    } else {
+        let dir = &from[..from.rfind('/').map(|i| i + 1).unwrap_or(0)];
+        format!("{}{}", dir, target)
+    };
+
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in joined.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            s => segments.push(s),
+        }
+    }
+    if let Some(last) = segments.pop() {
+        let last = last.strip_suffix(".md").unwrap_or(last);
+        if last != "index" {
+            segments.push(last);
+        }
+    }
+    Some(format!("/{}", segments.join("/")))
+}
+
+fn same_url(a: &str, b: &str) -> bool {
+    a.trim_end_matches('/') == b.trim_end_matches('/')
+}
+
+/// Whether `entry` links to the document at `url`.
+pub fn links_to(entry: &Entry, url: &str) -> bool {
+    let from = entry.file_url();
+    entry
+        .links
+        .iter()
+        .filter_map(|href| resolve(&from, href))
+        .any(|target| same_url(&target, url))
+}
+
+pub fn backlinks<'a>(url: &str, entries: &'a [Entry]) -> Vec<&'a Entry> {
+    entries
+        .iter()
+        .filter(|e| !same_url(&e.file_url(), url) && links_to(e, url))
+        .collect()
+}
+
+pub fn section(url: &str, entries: &[Entry]) -> String {
+    let linking = backlinks(url, entries);
+    if linking.is_empty() {
+        return String::new();
+    }
+    format!(
+        "<section class=\"backlinks\">\n<h2>Pages linking here</h2>\n{}</section>\n",
+        crate::listing::entry_list(linking)
+    )
+}
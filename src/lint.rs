@@ -0,0 +1,299 @@
+use crate::config;
+use crate::frontmatter::{self, FrontMatter};
+use crate::toc;
+use comrak::nodes::{AstNode, NodeValue};
+use comrak::{parse_document, Arena, ComrakOptions};
+use serde::Serialize;
+use serde_yaml::Value;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Off,
+    Warning,
+    Error,
+}
+
+impl Level {
+    fn parse(s: &str) -> Option<Level> {
+        match s {
+            "off" => Some(Level::Off),
+            "warning" | "warn" => Some(Level::Warning),
+            "error" => Some(Level::Error),
+            _ => None,
+        }
+    }
+}
+
+/// Every check, with how much it matters unless the config says otherwise.
+const RULES: &[(&str, Level)] = &[
+    ("missing-title", Level::Warning),
+    ("duplicate-heading-id", Level::Error),
+    ("bare-url", Level::Warning),
+    ("image-alt", Level::Error),
+    ("front-matter", Level::Error),
+    ("schema", Level::Error),
+];
+
+pub struct Levels(HashMap<&'static str, Level>);
+
+impl Levels {
+    pub fn new(config: &config::Lint) -> Result<Levels, String> {
+        let mut levels: HashMap<&'static str, Level> = RULES.iter().cloned().collect();
+        for (name, level) in config.rules.iter() {
+            let rule = RULES
+                .iter()
+                .map(|(rule, _)| *rule)
+                .find(|rule| *rule == name.as_str())
+                .ok_or_else(|| format!("unknown lint rule {}", name))?;
+            let level =
+                Level::parse(level).ok_or_else(|| format!("{}: not a level: {}", name, level))?;
+            levels.insert(rule, level);
+        }
+        Ok(Levels(levels))
+    }
+
+    fn of(&self, rule: &str) -> Level {
+        self.0.get(rule).cloned().unwrap_or(Level::Off)
+    }
+}
+
+#[derive(Serialize)]
+pub struct Problem {
+    pub path: String,
+    pub line: u32,
+    pub rule: &'static str,
+    pub level: Level,
+    pub message: String,
+}
+
+struct Found {
+    line: u32,
+    rule: &'static str,
+    message: String,
+}
+
+// Inline nodes don't know their line, the block holding them does.
+fn line_of<'a>(node: &'a AstNode<'a>) -> u32 {
+    node.ancestors()
+        .map(|n| n.data.borrow().start_line)
+        .find(|line| *line > 0)
+        .unwrap_or(1)
+}
+
+fn in_link<'a>(node: &'a AstNode<'a>) -> bool {
+    node.ancestors()
+        .any(|n| matches!(n.data.borrow().value, NodeValue::Link(_)))
+}
+
+fn check_front_matter(front_matter: &FrontMatter, found: &mut Vec<Found>) {
+    let kinds: &[(&str, &str)] = &[
+        ("title", "string"),
+        ("permalink", "string"),
+        ("template", "string"),
+        ("publish_at", "string"),
+        ("canonical", "string"),
+        ("protected", "string"),
+        ("password", "string"),
+        ("draft", "boolean"),
+        ("smart", "boolean"),
+        ("noindex", "boolean"),
+        ("tags", "list"),
+        ("aliases", "list"),
+    ];
+    for (key, kind) in kinds {
+        let fits = match (front_matter.get(key), *kind) {
+            (None, _) => true,
+            (Some(Value::String(_)), "string") => true,
+            (Some(Value::Bool(_)), "boolean") => true,
+            (Some(Value::String(_)), "list") => true,
+            (Some(Value::Sequence(items)), "list") => items.iter().all(Value::is_string),
+            _ => false,
+        };
+        if !fits {
+            found.push(Found {
+                line: 1,
+                rule: "front-matter",
+                message: format!("`{}` should be a {}", key, kind),
+            });
+        }
+    }
+}
+
+fn check(input: &str, schema: &config::Schema) -> Vec<Found> {
+    let mut found = Vec::new();
+    let (front_matter, body) = frontmatter::split(input);
+    if body.len() == input.len() && (input.starts_with("---\n") || input.starts_with("---\r\n")) {
+        found.push(Found {
+            line: 1,
+            rule: "front-matter",
+            message: String::from("front matter is not valid YAML or is not closed"),
+        });
+    }
+    check_front_matter(&front_matter, &mut found);
+    found.extend(
+        crate::schema::validate(schema, &front_matter)
+            .into_iter()
+            .map(|message| Found {
+                line: 1,
+                rule: "schema",
+                message,
+            }),
+    );
+    let offset = input[..input.len() - body.len()].lines().count() as u32;
+
+    // without autolinks, so bare URLs stay text
+    let options = ComrakOptions {
+        ext_autolink: false,
+        ..crate::render::DEFAULT.comrak.clone()
+    };
+    let arena = Arena::new();
+    let root = parse_document(&arena, body, &options);
+
+    let mut headings = false;
+    let mut slugs = HashSet::new();
+    for node in root.descendants() {
+        let line = offset + line_of(node);
+        match node.data.borrow().value {
+            NodeValue::Heading(_) => {
+                headings = true;
+                let text = toc::text_of(node);
+                let slug = toc::slug(&text);
+                if !slugs.insert(slug.clone()) {
+                    found.push(Found {
+                        line,
+                        rule: "duplicate-heading-id",
+                        message: format!("another heading already has the id #{}", slug),
+                    });
+                }
+            }
+            NodeValue::Image(_) => {
+                if toc::text_of(node).trim().is_empty() {
+                    found.push(Found {
+                        line,
+                        rule: "image-alt",
+                        message: String::from("image without alt text"),
+                    });
+                }
+            }
+            NodeValue::Text(ref text) => {
+                let text = String::from_utf8_lossy(text);
+                if (text.contains("http://") || text.contains("https://")) && !in_link(node) {
+                    found.push(Found {
+                        line,
+                        rule: "bare-url",
+                        message: String::from("URL outside of a link"),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    if front_matter.get_str("title").is_none() && !headings {
+        found.push(Found {
+            line: 1,
+            rule: "missing-title",
+            message: String::from("no title in front matter and no heading"),
+        });
+    }
+    found
+}
+
+/// Checks every document below `base_dir`, problems sorted by path and line.
+pub fn run(
+    base_dir: &Path,
+    symlinks: crate::symlinks::Policy,
+    levels: &Levels,
+    schema: &config::Schema,
+) -> Vec<Problem> {
+    let mut documents = Vec::new();
+    crate::index::walk(symlinks, base_dir, base_dir, &mut documents);
+    documents.sort();
+
+    let mut problems = Vec::new();
+    for (path, _) in documents {
+        let rel = path
+            .strip_prefix(base_dir)
+            .unwrap_or(&path)
+            .to_string_lossy()
+            .replace('\\', "/");
+        let input = match crate::encoding::read_to_string(&path) {
+            Ok(input) => input,
+            Err(err) => {
+                eprintln!("{}: {}", rel, err);
+                continue;
+            }
+        };
+        let mut found = check(&input, schema);
+        found.sort_by_key(|f| f.line);
+        for f in found {
+            let level = levels.of(f.rule);
+            if level != Level::Off {
+                problems.push(Problem {
+                    path: rel.clone(),
+                    line: f.line,
+                    rule: f.rule,
+                    level,
+                    message: f.message,
+                });
+            }
+        }
+    }
+    problems
+}
+
+/// Workflow commands end at a newline, so the message is percent-escaped.
+fn data(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Properties are also split on `,` and `:`.
+fn property(text: &str) -> String {
+    data(text).replace(':', "%3A").replace(',', "%2C")
+}
+
+/// One line per problem: `text` for people, `github` for workflow
+/// annotations, or a JSON array.
+pub fn report(problems: &[Problem], format: &str) -> String {
+    match format {
+        "json" => serde_json::to_string_pretty(problems).unwrap_or_default(),
+        "github" => problems
+            .iter()
+            .map(|p| {
+                format!(
+                    "::{} file={},line={},title={}::{}\n",
+                    if p.level == Level::Error {
+                        "error"
+                    } else {
+                        "warning"
+                    },
+                    property(&p.path),
+                    p.line,
+                    property(p.rule),
+                    data(&p.message)
+                )
+            })
+            .collect(),
+        _ => problems
+            .iter()
+            .map(|p| {
+                format!(
+                    "{}:{}: {} [{}] {}\n",
+                    p.path,
+                    p.line,
+                    if p.level == Level::Error {
+                        "error"
+                    } else {
+                        "warning"
+                    },
+                    p.rule,
+                    p.message
+                )
+            })
+            .collect(),
+    }
+}
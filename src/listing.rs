@@ -0,0 +1,210 @@
+use crate::index::Entry;
+use std::collections::HashMap;
+
+pub fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+pub fn link(entry: &Entry) -> String {
+    format!(
+        "<a href=\"{}\">{}</a>",
+        escape(&entry.url()),
+        escape(&entry.title)
+    )
+}
+
+pub fn entry_list<'a, I>(entries: I) -> String
+where
+    I: IntoIterator<Item = &'a Entry>,
+{
+    let items: Vec<String> = entries
+        .into_iter()
+        .map(|e| format!("<li>{}</li>", link(e)))
+        .collect();
+    format!("<ul>\n{}\n</ul>\n", items.join("\n"))
+}
+
+/// How a directory listing is ordered, from `?sort=` and `?order=`.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Sort {
+    Name,
+    Modified,
+    Title,
+}
+
+pub struct Query {
+    pub sort: Sort,
+    pub descending: bool,
+    /// From 1.
+    pub page: usize,
+    pub per_page: usize,
+}
+
+pub const PER_PAGE: usize = 100;
+
+impl Query {
+    /// Newest first when sorting by date, unless `order` says otherwise.
+    pub fn parse(query: &HashMap<String, String>) -> Query {
+        let sort = match query.get("sort").map(String::as_str) {
+            Some("mtime") | Some("modified") => Sort::Modified,
+            Some("title") => Sort::Title,
+            _ => Sort::Name,
+        };
+        let descending = match query.get("order").map(String::as_str) {
+            Some("desc") => true,
+            Some("asc") => false,
+            _ => sort == Sort::Modified,
+        };
+        let number = |key: &str, default: usize| {
+            query
+                .get(key)
+                .and_then(|n| n.parse::<usize>().ok())
+                .filter(|n| *n > 0)
+                .unwrap_or(default)
+        };
+        Query {
+            sort,
+            descending,
+            page: number("page", 1),
+            per_page: number("per_page", PER_PAGE).min(1000),
+        }
+    }
+
+    fn href(&self, sort: Sort, descending: bool, page: usize) -> String {
+        let sort = match sort {
+            Sort::Name => "name",
+            Sort::Modified => "mtime",
+            Sort::Title => "title",
+        };
+        let mut href = format!(
+            "?sort={}&order={}",
+            sort,
+            if descending { "desc" } else { "asc" }
+        );
+        if page > 1 {
+            href.push_str(&format!("&page={}", page));
+        }
+        if self.per_page != PER_PAGE {
+            href.push_str(&format!("&per_page={}", self.per_page));
+        }
+        href
+    }
+}
+
+/// A directory without an index page, its documents and subdirectories.
+pub struct Directory<'a> {
+    /// Ends with a `/`.
+    pub url: &'a str,
+    pub dirs: Vec<String>,
+    pub entries: Vec<&'a Entry>,
+}
+
+fn file_name(entry: &Entry) -> String {
+    entry
+        .path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+fn sorting(query: &Query) -> String {
+    [
+        (Sort::Name, "name"),
+        (Sort::Title, "title"),
+        (Sort::Modified, "date"),
+    ]
+    .iter()
+    .map(|(sort, label)| {
+        if *sort == query.sort {
+            // the current one flips the order
+            format!(
+                "<a class=\"current\" href=\"{}\">{} {}</a>",
+                escape(&query.href(*sort, !query.descending, 1)),
+                label,
+                if query.descending { "↓" } else { "↑" }
+            )
+        } else {
+            format!(
+                "<a href=\"{}\">{}</a>",
+                escape(&query.href(*sort, *sort == Sort::Modified, 1)),
+                label
+            )
+        }
+    })
+    .collect::<Vec<String>>()
+    .join(" · ")
+}
+
+fn pagination(query: &Query, pages: usize) -> String {
+    if pages < 2 {
+        return String::new();
+    }
+    let mut links = Vec::new();
+    if query.page > 1 {
+        links.push(format!(
+            "<a rel=\"prev\" href=\"{}\">previous</a>",
+            escape(&query.href(query.sort, query.descending, query.page - 1))
+        ));
+    }
+    links.push(format!("page {} of {}", query.page.min(pages), pages));
+    if query.page < pages {
+        links.push(format!(
+            "<a rel=\"next\" href=\"{}\">next</a>",
+            escape(&query.href(query.sort, query.descending, query.page + 1))
+        ));
+    }
+    links.join(" · ")
+}
+
+/// One page of the listing, in the `listing.html` template whose
+/// `{{title}}`, `{{sorting}}`, `{{items}}` and `{{pagination}}` it fills.
+pub fn directory(template: &str, mut dir: Directory, query: &Query) -> String {
+    dir.entries.sort_by(|a, b| {
+        let order = match query.sort {
+            Sort::Name => file_name(a).cmp(&file_name(b)),
+            Sort::Modified => a.modified.cmp(&b.modified),
+            Sort::Title => a.title.to_lowercase().cmp(&b.title.to_lowercase()),
+        };
+        if query.descending {
+            order.reverse()
+        } else {
+            order
+        }
+    });
+    dir.dirs.sort();
+    let pages = (dir.entries.len() + query.per_page - 1) / query.per_page;
+    let mut items: Vec<String> = Vec::new();
+    if query.page == 1 {
+        items.extend(dir.dirs.iter().map(|name| {
+            format!(
+                "<li class=\"dir\"><a href=\"{}{}/\">{}/</a></li>",
+                escape(dir.url),
+                escape(name),
+                escape(name)
+            )
+        }));
+    }
+    items.extend(
+        dir.entries
+            .iter()
+            .skip((query.page - 1) * query.per_page)
+            .take(query.per_page)
+            .map(|e| format!("<li>{}</li>", link(e))),
+    );
+    template
+        .replace("{{title}}", &escape(dir.url))
+        .replace("{{sorting}}", &sorting(query))
+        .replace("{{items}}", &items.join("\n"))
+        .replace("{{pagination}}", &pagination(query, pages))
+}
@@ -1,41 +1,121 @@
 #[macro_use]
 extern crate lazy_static;
-use ammonia;
-use clap::{App, Arg};
-use comrak::{markdown_to_html, ComrakOptions};
+use clap::{App, Arg, SubCommand};
 use std::collections::HashMap;
 use std::convert::TryInto;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tokio::{self, io::AsyncReadExt, sync::Mutex};
 use warp::{self, Filter, Rejection};
 
+mod analytics;
+mod annotations;
+mod archive;
+mod auth;
+mod book;
+mod browser;
+mod build;
+mod cache_control;
+mod comments;
+mod config;
+mod diff;
+mod doctor;
+mod encoding;
+mod etag;
+mod events;
+mod frontmatter;
+mod graph;
+mod index;
+mod indexing;
+mod jobs;
+mod lang;
+mod last_modified;
+mod links;
+mod lint;
+mod listing;
+mod man;
+mod mdbook;
+mod overrides;
+mod pandoc;
+mod reading;
+mod recent;
+mod redirects;
+mod render;
+mod schema;
+mod search;
+mod server;
+mod settings;
+mod slides;
+mod snippets;
+mod source;
+mod standalone;
+mod stats;
+mod switcher;
+mod symlinks;
+mod tags;
+mod template;
+mod toc;
+mod tree;
+mod upload;
+
+use frontmatter::FrontMatter;
+
 #[derive(Debug)]
 enum MarkdownError {
     NotMarkdown,
-    // Decoding,
+    Decoding(PathBuf, encoding::Undecodable),
 }
 
 impl warp::reject::Reject for MarkdownError {}
 
-const HTML_HEAD_STR: &'static str = include_str!("html/head.html");
-const HTML_TAIL_STR: &'static str = include_str!("html/tail.html");
+struct Rendered {
+    body: String,
+    lang: String,
+    templates: ::std::sync::Arc<template::Templates>,
+    cache_control: http::HeaderValue,
+    /// Goes at the end of the head's `<head>`.
+    meta: String,
+    /// Goes at the end of the tail's body.
+    analytics: String,
+    vars: template::Vars,
+}
 
-struct Rendered(String);
+impl Rendered {
+    fn html(&self) -> String {
+        let head = indexing::with_meta(
+            &template::fill(&self.templates.head(), &self.vars),
+            &self.meta,
+        );
+        let tail = template::fill(&self.templates.tail(), &self.vars);
+        let tail = match tail.rfind("</body>") {
+            Some(end) => [&tail[..end], &self.analytics, &tail[end..]].join(""),
+            None => [tail.as_str(), &self.analytics].join(""),
+        };
+        [head.as_str(), &self.body, &tail].join("")
+    }
+}
 
 impl warp::Reply for Rendered {
     fn into_response(self) -> warp::reply::Response {
-        let body: String = [
-            String::from(HTML_HEAD_STR),
-            self.0,
-            String::from(HTML_TAIL_STR),
-        ]
-        .join("");
+        let body = self.html();
+        let etag = etag::of(body.as_bytes());
         let mut response = warp::reply::Response::new(body.into());
         *response.status_mut() = http::StatusCode::OK;
-        response.headers_mut().insert(
+        let headers = response.headers_mut();
+        headers.insert(
             http::header::CONTENT_TYPE,
             http::HeaderValue::from_static("text/html; charset=UTF-8"),
         );
+        headers.insert(
+            http::header::VARY,
+            http::HeaderValue::from_static("accept, accept-language"),
+        );
+        if let Ok(lang) = http::HeaderValue::from_str(&self.lang) {
+            headers.insert(http::header::CONTENT_LANGUAGE, lang);
+        }
+        headers.insert(http::header::CACHE_CONTROL, self.cache_control);
+        if let Ok(tag) = http::HeaderValue::from_str(&etag) {
+            headers.insert(http::header::ETAG, tag);
+        }
         response
     }
 }
@@ -46,35 +126,323 @@ struct CacheKey {
     modified: ::std::time::SystemTime,
 }
 
-type Cache = ::std::sync::Arc<Mutex<HashMap<CacheKey, String>>>;
+#[derive(Clone)]
+struct Page {
+    html: String,
+    front_matter: FrontMatter,
+    title: String,
+    toc: Vec<toc::Heading>,
+    modified: ::std::time::SystemTime,
+    /// Hash of `html`, stable across mtime changes that don't change the output.
+    etag: String,
+    words: usize,
+    attachments: Vec<links::Attachment>,
+    /// Of the last commit touching the file, with `last_modified.source = "git"`.
+    committed: Option<::std::time::SystemTime>,
+    /// Files its code blocks come from, a change there renders it again.
+    snippets: Vec<(PathBuf, Option<::std::time::SystemTime>)>,
+}
+
+impl Page {
+    fn is_protected(&self) -> bool {
+        self.front_matter.get_str("protected").is_some()
+            || self.front_matter.get_str("password").is_some()
+    }
+}
+
+type Cache = ::std::sync::Arc<Mutex<HashMap<CacheKey, Page>>>;
 
 #[derive(Clone)]
 struct Context {
     base_dir: PathBuf,
     cache: Cache,
+    default_lang: String,
+    drafts: bool,
+    users: ::std::sync::Arc<auth::Users>,
+    index: index::Index,
+    recent_widget: Option<usize>,
+    book: Option<::std::sync::Arc<mdbook::Book>>,
+    options: ::std::sync::Arc<render::Options>,
+    static_cache: ::std::sync::Arc<cache_control::Rules>,
+    templates: ::std::sync::Arc<template::Templates>,
+    theme_dir: Option<PathBuf>,
+    edit: bool,
+    upload_dir: PathBuf,
+    pandoc: Option<PathBuf>,
+    stats: ::std::sync::Arc<stats::Stats>,
+    page_cache: http::HeaderValue,
+    sources: ::std::sync::Arc<settings::Sources>,
+    settings: ::std::sync::Arc<::std::sync::RwLock<settings::Settings>>,
+    /// Latest change to the `.mdserve.toml` files applied, if any.
+    overrides: Option<::std::time::SystemTime>,
+    symlinks: symlinks::Policy,
+    redirects: ::std::sync::Arc<redirects::Redirects>,
+    /// Trees served for other host names, from `--vhost`.
+    vhosts: ::std::sync::Arc<HashMap<String, Context>>,
+    access: ::std::sync::Arc<Vec<config::Access>>,
+    upstream: ::std::sync::Arc<auth::Upstream>,
+    comments: ::std::sync::Arc<config::Comments>,
+    analytics: ::std::sync::Arc<config::Analytics>,
+    last_modified: ::std::sync::Arc<config::LastModified>,
+    schema: ::std::sync::Arc<config::Schema>,
+    events: ::std::sync::Arc<events::Events>,
+    jobs: ::std::sync::Arc<jobs::Jobs>,
+    /// The `Host` header of the request.
+    host: Option<String>,
+    /// Address of the client, or of the proxy in front.
+    remote: Option<::std::net::IpAddr>,
 }
 
-lazy_static! {
-    static ref CLEANER: ammonia::Builder<'static> = {
-        let mut d = ammonia::Builder::default();
-        d.add_generic_attributes(&["id", "class"]);
-        d
-    };
-    static ref CM_OPTIONS: ComrakOptions = ComrakOptions {
-        smart: true,
-        unsafe_: true,
-        ext_superscript: true,
-        ext_autolink: true,
-        ext_table: true,
-        ext_header_ids: Some(String::new()),
-        ..ComrakOptions::default()
-    };
+/// A `Host` header without its port.
+fn host_name(host: &str) -> &str {
+    match host.rfind(':') {
+        Some(i) if !host[i..].contains(']') => &host[..i],
+        _ => host,
+    }
+}
+
+impl Context {
+    /// The context of the tree served for a `Host` header.
+    fn for_host(&self, host: Option<&str>) -> &Context {
+        host.map(|h| host_name(h).to_lowercase())
+            .and_then(|h| self.vhosts.get(&h))
+            .unwrap_or(self)
+    }
+
+    /// A copy for one request, holding the settings in force when it came in.
+    fn current(&self) -> Context {
+        let settings = self
+            .settings
+            .read()
+            .expect("settings lock poisoned")
+            .clone();
+        Context {
+            users: settings.users,
+            options: settings.options,
+            static_cache: settings.static_cache,
+            templates: settings.templates,
+            page_cache: settings.page_cache,
+            redirects: settings.redirects,
+            access: settings.access,
+            comments: settings.comments,
+            analytics: settings.analytics,
+            last_modified: settings.last_modified,
+            schema: settings.schema,
+            ..self.clone()
+        }
+    }
+
+    fn identity(&self, headers: &http::HeaderMap) -> Option<auth::Identity> {
+        auth::identify(&self.upstream, &self.users, headers, self.remote)
+    }
+
+    /// Whose an export job is: the reader's name, or where an anonymous
+    /// reader connects from. Names never hold a `:`, so the two can't meet.
+    fn job_owner(&self, identity: Option<&auth::Identity>) -> Option<String> {
+        match (identity, self.remote) {
+            (Some(identity), _) => Some(identity.user.clone()),
+            (None, Some(ip)) => Some(format!("anonymous:{}", ip)),
+            (None, None) => None,
+        }
+    }
+
+    /// The context for a page, with the `.mdserve.toml` files of its
+    /// directory and the ones above layered on the global settings.
+    fn within(&self, page: &Path) -> Context {
+        let local = match page
+            .parent()
+            .and_then(|dir| overrides::load(&self.base_dir, dir))
+        {
+            Some(local) => local,
+            None => return self.clone(),
+        };
+        let mut options = (*self.options).clone();
+        options.apply(&local.config);
+        let templates = match local.config.pages.theme.as_ref() {
+            Some(theme) => self.templates.with_theme(theme),
+            None => self.templates.clone(),
+        };
+        let page_cache = local
+            .config
+            .pages
+            .cache_control
+            .as_ref()
+            .and_then(|value| http::HeaderValue::from_str(value).ok())
+            .unwrap_or_else(|| self.page_cache.clone());
+        Context {
+            options: ::std::sync::Arc::new(options),
+            templates,
+            page_cache,
+            overrides: Some(local.modified),
+            ..self.clone()
+        }
+    }
+
+    /// Reads the config and users files again, for every host. On error
+    /// the running settings stay as they are.
+    async fn reload(&self) -> Result<(), String> {
+        for context in ::std::iter::once(self).chain(self.vhosts.values()) {
+            let settings = settings::load(&context.sources)?;
+            *context.settings.write().expect("settings lock poisoned") = settings;
+            context.cache.lock().await.clear();
+        }
+        Ok(())
+    }
+}
+
+fn is_draft_path(path: &Path) -> bool {
+    path.components().any(|c| c.as_os_str() == "_drafts")
+}
+
+/// A 404 that, unlike a rejection, won't fall through to the static files.
+fn not_found() -> warp::reply::Response {
+    let mut response = warp::reply::Response::new("Not Found".into());
+    *response.status_mut() = http::StatusCode::NOT_FOUND;
+    response
+}
+
+fn bad_request(message: String) -> warp::reply::Response {
+    let mut response = warp::reply::Response::new(message.into());
+    *response.status_mut() = http::StatusCode::BAD_REQUEST;
+    response
+}
+
+fn server_error(message: String) -> warp::reply::Response {
+    let mut response = warp::reply::Response::new(message.into());
+    *response.status_mut() = http::StatusCode::INTERNAL_SERVER_ERROR;
+    response
+}
+
+/// Why a document can't be shown, rather than a bare 500.
+fn undecodable(
+    context: &Context,
+    path: &Path,
+    err: &encoding::Undecodable,
+) -> warp::reply::Response {
+    let name = path.strip_prefix(&context.base_dir).unwrap_or(path);
+    let mut response = generated(
+        context,
+        format!(
+            "<h1>Cannot read {}</h1>\n<p class=\"decoding-error\">{}.</p>\n",
+            listing::escape(&name.to_string_lossy()),
+            err
+        ),
+    );
+    *response.status_mut() = http::StatusCode::INTERNAL_SERVER_ERROR;
+    response
+}
+
+fn forbidden() -> warp::reply::Response {
+    let mut response = warp::reply::Response::new("Forbidden".into());
+    *response.status_mut() = http::StatusCode::FORBIDDEN;
+    response
+}
+
+fn redirect(status: http::StatusCode, location: &str) -> warp::reply::Response {
+    let mut response = warp::reply::Response::new(Default::default());
+    *response.status_mut() = status;
+    if let Ok(location) = http::HeaderValue::from_str(location) {
+        response
+            .headers_mut()
+            .insert(http::header::LOCATION, location);
+    }
+    response
+}
+
+/// Where a request for a directory or its index should go instead, so each
+/// page has one URL and relative links in it resolve from its directory.
+fn canonical(base_dir: &Path, req_path: &str) -> Option<String> {
+    for index in &["index.md", "index"] {
+        if let Some(dir) = req_path.strip_suffix(index) {
+            if dir.ends_with('/') {
+                return Some(String::from(dir));
+            }
+        }
+    }
+    let rel = req_path.trim_start_matches('/');
+    if !req_path.ends_with('/') && base_dir.join(rel).is_dir() {
+        return Some(format!("{}/", req_path));
+    }
+    None
+}
+
+fn with_query(path: &str, query: &HashMap<String, String>) -> String {
+    use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+
+    if query.is_empty() {
+        return String::from(path);
+    }
+    let pairs: Vec<String> = query
+        .iter()
+        .map(|(k, v)| {
+            format!(
+                "{}={}",
+                utf8_percent_encode(k, NON_ALPHANUMERIC),
+                utf8_percent_encode(v, NON_ALPHANUMERIC)
+            )
+        })
+        .collect();
+    format!("{}?{}", path, pairs.join("&"))
+}
+
+fn unauthorized() -> warp::reply::Response {
+    let mut response = warp::reply::Response::new("Unauthorized".into());
+    *response.status_mut() = http::StatusCode::UNAUTHORIZED;
+    response.headers_mut().insert(
+        http::header::WWW_AUTHENTICATE,
+        http::HeaderValue::from_static("Basic realm=\"mdserve\", charset=\"UTF-8\""),
+    );
+    response
 }
 
-fn process(input: &str) -> String {
-    CLEANER
-        .clean(&markdown_to_html(input, &CM_OPTIONS))
-        .to_string()
+fn header_str<'a>(headers: &'a http::HeaderMap, name: http::header::HeaderName) -> Option<&'a str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+fn process(
+    input: &str,
+    path: &Path,
+    modified: ::std::time::SystemTime,
+    options: &render::Options,
+) -> Page {
+    let (front_matter, body) = frontmatter::split(input);
+    let render::Document {
+        html,
+        toc,
+        words,
+        attachments,
+        snippets,
+    } = render::render_document(body, &options.for_page(&front_matter));
+    let html = if front_matter
+        .get_bool("reading_time")
+        .unwrap_or(options.reading_time)
+    {
+        reading::with_badge(&html, words)
+    } else {
+        html
+    };
+    let title = front_matter
+        .get_str("title")
+        .map(String::from)
+        .or_else(|| toc.first().map(|h| h.text.clone()))
+        .unwrap_or_else(|| {
+            path.file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        });
+    let etag = etag::of(html.as_bytes());
+    Page {
+        html,
+        front_matter,
+        title,
+        toc,
+        modified,
+        etag,
+        words,
+        attachments,
+        committed: None,
+        snippets: snippets::stamp(snippets),
+    }
 }
 
 async fn file_metadata(f: &tokio::fs::File) -> Result<::std::fs::Metadata, Rejection> {
@@ -84,15 +452,16 @@ async fn file_metadata(f: &tokio::fs::File) -> Result<::std::fs::Metadata, Rejec
     }
 }
 
-async fn read_file(f: &mut tokio::fs::File, size: u64) -> Result<String, Rejection> {
-    let mut buf = String::with_capacity(size.try_into().unwrap());
-    match f.read_to_string(&mut buf).await {
-        Ok(_) => Ok(buf),
+async fn read_file(f: &mut tokio::fs::File, path: &Path, size: u64) -> Result<String, Rejection> {
+    let mut buf = Vec::with_capacity(size.try_into().unwrap());
+    match f.read_to_end(&mut buf).await {
+        Ok(_) => encoding::decode(buf)
+            .map_err(|err| warp::reject::custom(MarkdownError::Decoding(path.to_path_buf(), err))),
         Err(_) => Err(warp::reject()),
     }
 }
 
-fn evict(path: &PathBuf, cache: &mut HashMap<CacheKey, String>) {
+fn evict(path: &PathBuf, cache: &mut HashMap<CacheKey, Page>) {
     let keys: Vec<CacheKey> = cache
         .keys()
         .filter(|k| &k.path == path)
@@ -104,37 +473,68 @@ fn evict(path: &PathBuf, cache: &mut HashMap<CacheKey, String>) {
     }
 }
 
-async fn process_file(path: &PathBuf, cache: Cache) -> Result<Rendered, Rejection> {
+fn cache_key(path: &PathBuf, meta: &::std::fs::Metadata, context: &Context) -> CacheKey {
+    let modified = meta.modified().expect("We want to run on a platform where https://doc.rust-lang.org/std/fs/struct.Metadata.html#method.modified is available");
+    CacheKey {
+        // a page renders anew when its directory settings change too
+        modified: context.overrides.map_or(modified, |o| o.max(modified)),
+        path: path.clone(),
+    }
+}
+
+/// A document's front matter, from the render cache if the page is there,
+/// otherwise read from the file without rendering anything.
+async fn front_matter_of(path: &PathBuf, context: &Context) -> Result<FrontMatter, Rejection> {
     let mut file = tokio::fs::File::open(path)
         .await
         .map_err(|_| warp::reject())?;
     let meta = file_metadata(&file).await?;
-    let ck = CacheKey {
-        modified: meta.modified().expect("We want to run on a platform where https://doc.rust-lang.org/std/fs/struct.Metadata.html#method.modified is available"),
-        path: path.clone(),
-    };
+    let ck = cache_key(path, &meta, context);
+    if let Some(page) = context.cache.lock().await.get(&ck) {
+        return Ok(page.front_matter.clone());
+    }
+    let input = read_file(&mut file, path, meta.len()).await?;
+    Ok(frontmatter::split(&input).0)
+}
+
+async fn process_file(path: &PathBuf, context: &Context) -> Result<Page, Rejection> {
+    let mut file = tokio::fs::File::open(path)
+        .await
+        .map_err(|_| warp::reject())?;
+    let meta = file_metadata(&file).await?;
+    let ck = cache_key(path, &meta, context);
 
-    let mut cache = cache.lock().await;
+    let mut cache = context.cache.lock().await;
 
-    match cache.get(&ck) {
-        Some(s) => Ok(Rendered(s.clone())),
+    match cache
+        .get(&ck)
+        .filter(|page| !snippets::changed(&page.snippets))
+    {
+        Some(s) => {
+            context.stats.hit();
+            Ok(s.clone())
+        }
         None => {
-            let input = read_file(&mut file, meta.len()).await?;
-            let output = process(&input);
+            let input = read_file(&mut file, path, meta.len()).await?;
+            let started = ::std::time::Instant::now();
+            let mut output = process(&input, path, ck.modified, &context.options);
+            if context.last_modified.source.as_deref() == Some("git") {
+                output.committed = last_modified::committed(path);
+            }
+            for violation in schema::validate(&context.schema, &output.front_matter) {
+                eprintln!("{}: {}", path.display(), violation);
+            }
+            context.stats.miss(path, started.elapsed());
             evict(path, &mut cache);
             cache.insert(ck, output.clone());
-            Ok(Rendered(output))
+            Ok(output)
         }
     }
 }
 
-async fn convert(
-    path: warp::filters::path::FullPath,
-    context: Context,
-) -> Result<impl warp::Reply, Rejection> {
-    let req_path_str = path.as_str();
+fn resolve(base_dir: &PathBuf, req_path_str: &str) -> Result<PathBuf, Rejection> {
     let req_path = PathBuf::from(req_path_str.get(1..).unwrap_or("index.md"));
-    let maybe_full_path = context.base_dir.clone().join(req_path.clone());
+    let maybe_full_path = base_dir.clone().join(req_path.clone());
     let full_path = if maybe_full_path.is_dir() {
         maybe_full_path.clone().join("index.md")
     } else {
@@ -142,12 +542,12 @@ async fn convert(
     };
 
     match full_path.extension() {
-        Some(ext) if ext == "md" => process_file(&full_path, context.cache).await,
+        Some(ext) if ext == "md" => Ok(full_path),
         Some(_) => Err(warp::reject::custom(MarkdownError::NotMarkdown)),
         None => {
             let full_path_ext = full_path.with_extension("md");
             if full_path_ext.exists() {
-                process_file(&full_path_ext, context.cache).await
+                Ok(full_path_ext)
             } else {
                 Err(warp::reject::not_found())
             }
@@ -155,79 +555,2262 @@ async fn convert(
     }
 }
 
-fn inject_context(ctx: Context) -> warp::filters::BoxedFilter<(Context,)> {
-    warp::any().map(move || ctx.clone()).boxed()
+struct Found {
+    /// With the settings of the page's directory.
+    context: Context,
+    identity: Option<auth::Identity>,
+    url: String,
+    negotiated: lang::Negotiated,
+    page: Page,
+}
+
+enum Lookup {
+    Found(Found),
+    Refused(warp::reply::Response),
+}
+
+/// A document that can't be read answered with an error page when it
+/// isn't text we know how to decode.
+fn refused(context: &Context, rejection: Rejection) -> Result<Lookup, Rejection> {
+    match rejection.find::<MarkdownError>() {
+        Some(MarkdownError::Decoding(path, err)) => {
+            Ok(Lookup::Refused(undecodable(context, path, err)))
+        }
+        _ => Err(rejection),
+    }
 }
 
-fn print_log(info: warp::filters::log::Info) {
-    use chrono::Utc;
-    eprintln!(
-        "{} {} {} {} {} {}",
-        Utc::now().to_rfc3339(),
-        info.remote_addr()
-            .map(|a| format!("{}", a.ip()))
-            .unwrap_or("-".into()),
-        info.method(),
-        info.path(),
-        info.status(),
-        info.elapsed().as_millis(),
+/// Finds and renders the document behind a request path, applying the
+/// language, draft and access rules shared by every route serving pages.
+async fn lookup(
+    req_path: &str,
+    query: &HashMap<String, String>,
+    headers: &http::HeaderMap,
+    context: &Context,
+) -> Result<Lookup, Rejection> {
+    let req_path = match context.book {
+        Some(_) => mdbook::source_path(req_path),
+        None => String::from(req_path),
+    };
+    if !context.drafts && is_draft_path(Path::new(&req_path)) {
+        return Ok(Lookup::Refused(not_found()));
+    }
+    let full_path = resolve(&context.base_dir, &req_path)?;
+    let negotiated = lang::negotiate(
+        &full_path,
+        query.get("lang").map(String::as_str),
+        header_str(headers, http::header::ACCEPT_LANGUAGE),
+        &context.default_lang,
+    );
+    if !symlinks::allowed(context.symlinks, &context.base_dir, &negotiated.path) {
+        return Ok(Lookup::Refused(not_found()));
+    }
+    let url = index::url_for(
+        full_path
+            .strip_prefix(&context.base_dir)
+            .unwrap_or(&full_path),
     );
+    let identity = context.identity(headers);
+    if !auth::allowed(&context.access, &url, identity.as_ref()) {
+        return Ok(Lookup::Refused(match identity {
+            Some(_) => forbidden(),
+            None => unauthorized(),
+        }));
+    }
+    let context = context.within(&negotiated.path);
+    // drafts and protected pages are turned down before paying for a render
+    let front_matter = match front_matter_of(&negotiated.path, &context).await {
+        Ok(front_matter) => front_matter,
+        Err(rejection) => return refused(&context, rejection),
+    };
+    if !context.drafts && front_matter.is_draft() {
+        return Ok(Lookup::Refused(not_found()));
+    }
+    if !auth::authorize(
+        &front_matter,
+        identity.as_ref(),
+        header_str(headers, http::header::AUTHORIZATION),
+    ) {
+        return Ok(Lookup::Refused(unauthorized()));
+    }
+    let page = match process_file(&negotiated.path, &context).await {
+        Ok(page) => page,
+        Err(rejection) => return refused(&context, rejection),
+    };
+    Ok(Lookup::Found(Found {
+        context,
+        identity,
+        url,
+        negotiated,
+        page,
+    }))
 }
 
-// #[tokio::main]
-async fn serve(argv0: String, argv1: String) {
-    let base_dir = PathBuf::from(&argv0);
-    let dir = warp::fs::dir(base_dir.clone());
-    let cache: Cache = ::std::sync::Arc::new(Mutex::new(HashMap::new()));
-    let ctx = Context {
-        base_dir: base_dir.clone(),
-        cache: cache,
+/// The nearest `name` file in the page's directory or its ancestors,
+/// rendered, as `_header.md` and `_footer.md` apply to a whole subtree.
+async fn partial(context: &Context, page_path: &Path, name: &str) -> Option<String> {
+    let mut dir = page_path.parent();
+    while let Some(d) = dir {
+        if !d.starts_with(&context.base_dir) {
+            break;
+        }
+        let candidate = d.join(name);
+        if candidate.is_file() {
+            return process_file(&candidate, context).await.ok().map(|p| p.html);
+        }
+        dir = d.parent();
+    }
+    None
+}
+
+async fn zip_archive(
+    req_path: &str,
+    query: &HashMap<String, String>,
+    headers: &http::HeaderMap,
+    context: &Context,
+) -> Result<warp::reply::Response, Rejection> {
+    let rel = Path::new(req_path.get(1..).unwrap_or(""));
+    let dir = context.base_dir.join(rel);
+    let escapes = rel
+        .components()
+        .any(|c| c == ::std::path::Component::ParentDir);
+    if escapes
+        || !dir.is_dir()
+        || (!context.drafts && is_draft_path(rel))
+        || !symlinks::allowed(context.symlinks, &context.base_dir, &dir)
+    {
+        return Ok(not_found());
+    }
+    let identity = context.identity(headers);
+    if !auth::allowed(&context.access, req_path, identity.as_ref()) {
+        return Ok(match identity {
+            Some(_) => forbidden(),
+            None => unauthorized(),
+        });
+    }
+
+    let name = dir
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| String::from("site"));
+    let archive = archive::Archive {
+        base_dir: context.base_dir.clone(),
+        dir,
+        drafts: context.drafts,
+        symlinks: context.symlinks,
+        rendered: if query.contains_key("rendered") {
+            Some((context.options.clone(), context.templates.clone()))
+        } else {
+            None
+        },
     };
-    let get = warp::get()
-        .and(warp::path::full())
-        .and(inject_context(ctx.clone()))
-        .and_then(convert)
-        .or(dir)
-        .with(warp::log::custom(print_log));
-    let service = warp::serve(get);
-    let addr: std::net::SocketAddr = argv1.parse().expect("not a valid address");
-    println!("running on http://{}", addr);
-    service.run(addr).await;
+    let access = context.access.clone();
+    let owner = context.job_owner(identity.as_ref());
+    let submitted = context.jobs.submit(owner.clone(), move || {
+        let visible = |url: &str| auth::allowed(&access, url, identity.as_ref());
+        archive::build(&archive, visible).map(|bytes| jobs::Output {
+            bytes,
+            mime: "application/zip",
+            filename: format!("{}.zip", name),
+        })
+    });
+    Ok(queued(context, headers, owner.as_deref(), submitted))
 }
 
-fn main() {
-    let base_dir = Arg::with_name("base_dir")
-        .short("d")
-        .long("dir")
-        .value_name("base_dir")
-        .help("Directory to serve")
-        .takes_value(true);
+/// `202 Accepted` for a queued export: a page following the job for
+/// browsers, its status for everyone else. An export that couldn't be
+/// queued gets a `429` when it's the reader's own jobs that are too many,
+/// a `503` when it's everyone's.
+fn queued(
+    context: &Context,
+    headers: &http::HeaderMap,
+    owner: Option<&str>,
+    submitted: Result<String, jobs::Busy>,
+) -> warp::reply::Response {
+    use warp::Reply;
 
-    let addr = Arg::with_name("address")
-        .short("a")
-        .long("address")
-        .value_name("address")
-        .help("address to listen to")
-        .takes_value(true);
+    let id = match submitted {
+        Ok(id) => id,
+        Err(busy) => {
+            let (status, message) = match busy {
+                jobs::Busy::Owner => (
+                    http::StatusCode::TOO_MANY_REQUESTS,
+                    "too many exports of yours, wait for them to finish or expire",
+                ),
+                jobs::Busy::Everyone => (
+                    http::StatusCode::SERVICE_UNAVAILABLE,
+                    "too many exports under way, try again later",
+                ),
+            };
+            let mut response = warp::reply::Response::new(message.into());
+            *response.status_mut() = status;
+            response.headers_mut().insert(
+                http::header::RETRY_AFTER,
+                http::HeaderValue::from_static("60"),
+            );
+            return response;
+        }
+    };
+    let id = id.as_str();
+    let browser = header_str(headers, http::header::ACCEPT)
+        .map(|a| a.contains("text/html"))
+        .unwrap_or(false);
+    let mut response = match context.jobs.status(id, owner).filter(|_| !browser) {
+        Some(status) => warp::reply::json(&status).into_response(),
+        None => generated(context, jobs::page(id)),
+    };
+    *response.status_mut() = http::StatusCode::ACCEPTED;
+    if let Ok(location) = http::HeaderValue::from_str(&jobs::url(id)) {
+        response
+            .headers_mut()
+            .insert(http::header::LOCATION, location);
+    }
+    response.headers_mut().insert(
+        http::header::CACHE_CONTROL,
+        http::HeaderValue::from_static("no-store"),
+    );
+    response
+}
 
-    let matches = App::new("mdserve")
-        .version("0.1")
-        .about("Serve you some markdown")
-        .arg(base_dir)
-        .arg(addr)
-        .get_matches();
+/// Where an export stands, or with `?download` the file it made.
+async fn job_status(
+    id: String,
+    query: HashMap<String, String>,
+    headers: http::HeaderMap,
+    context: Context,
+) -> Result<warp::reply::Response, Rejection> {
+    use warp::Reply;
 
-    let argv0 = matches.value_of("base_dir");
-    let argv1 = matches.value_of("address");
+    let owner = context.job_owner(context.identity(&headers).as_ref());
+    let owner = owner.as_deref();
+    if !query.contains_key("download") {
+        return Ok(match context.jobs.status(&id, owner) {
+            Some(status) => {
+                let mut response = warp::reply::json(&status).into_response();
+                response.headers_mut().insert(
+                    http::header::CACHE_CONTROL,
+                    http::HeaderValue::from_static("no-store"),
+                );
+                response
+            }
+            None => not_found(),
+        });
+    }
+    let output = match context.jobs.output(&id, owner) {
+        Some(output) => output,
+        None => return Ok(not_found()),
+    };
+    let mut response = warp::reply::Response::new(output.bytes.clone().into());
+    let headers = response.headers_mut();
+    headers.insert(
+        http::header::CONTENT_TYPE,
+        http::HeaderValue::from_static(output.mime),
+    );
+    if let Ok(disposition) =
+        http::HeaderValue::from_str(&format!("attachment; filename=\"{}\"", output.filename))
+    {
+        headers.insert(http::header::CONTENT_DISPOSITION, disposition);
+    }
+    Ok(response)
+}
 
-    match (argv0, argv1) {
-        (Some(base_dir), Some(addr)) => {
+/// Whether the directory at `req_path` has an index page, in any language.
+/// Anything that isn't a directory counts as having one.
+fn has_index(base_dir: &Path, req_path: &str) -> bool {
+    let dir = base_dir.join(req_path.trim_start_matches('/'));
+    match ::std::fs::read_dir(&dir) {
+        Ok(entries) => entries.filter_map(|e| e.ok()).any(|e| {
+            let name = e.file_name();
+            let name = name.to_string_lossy();
+            name == "index.md" || (name.starts_with("index.") && name.ends_with(".md"))
+        }),
+        Err(_) => true,
+    }
+}
+
+async fn directory_listing(
+    req_path: &str,
+    query: &HashMap<String, String>,
+    headers: &http::HeaderMap,
+    context: &Context,
+) -> Result<warp::reply::Response, Rejection> {
+    let rel = Path::new(req_path.trim_start_matches('/'));
+    let dir = context.base_dir.join(rel);
+    let escapes = rel
+        .components()
+        .any(|c| c == ::std::path::Component::ParentDir);
+    let identity = context.identity(headers);
+    if escapes
+        || (!context.drafts && is_draft_path(rel))
+        || !symlinks::allowed(context.symlinks, &context.base_dir, &dir)
+    {
+        return Ok(not_found());
+    }
+    if !auth::allowed(&context.access, req_path, identity.as_ref()) {
+        return Ok(match identity {
+            Some(_) => forbidden(),
+            None => unauthorized(),
+        });
+    }
+    let context = context.within(&dir.join("index.md"));
+
+    let entries = visible(&context, identity.as_ref()).await;
+    let mut listed = listing::Directory {
+        url: req_path,
+        dirs: Vec::new(),
+        entries: Vec::new(),
+    };
+    for entry in entries.iter() {
+        let below = match entry.path.strip_prefix(rel) {
+            Ok(below) => below,
+            Err(_) => continue,
+        };
+        let mut components = below.components();
+        match (components.next(), components.next()) {
+            (Some(name), Some(_)) => {
+                let name = name.as_os_str().to_string_lossy().into_owned();
+                if !listed.dirs.contains(&name) {
+                    listed.dirs.push(name);
+                }
+            }
+            (Some(_), None) => listed.entries.push(entry),
+            _ => {}
+        }
+    }
+    let body = listing::directory(
+        &context.templates.listing.get(),
+        listed,
+        &listing::Query::parse(query),
+    );
+    Ok(personal(generated(&context, body), identity.as_ref()))
+}
+
+async fn book_view(
+    req_path: &str,
+    headers: &http::HeaderMap,
+    context: &Context,
+) -> Result<warp::reply::Response, Rejection> {
+    let rel = Path::new(req_path.get(1..).unwrap_or(""));
+    let dir = context.base_dir.join(rel);
+    let escapes = rel
+        .components()
+        .any(|c| c == ::std::path::Component::ParentDir);
+    if escapes
+        || !dir.is_dir()
+        || (!context.drafts && is_draft_path(rel))
+        || !symlinks::allowed(context.symlinks, &context.base_dir, &dir)
+    {
+        return Ok(not_found());
+    }
+    let identity = context.identity(headers);
+    let context = context.within(&dir.join("index.md"));
+
+    let book = book::Book {
+        base_dir: context.base_dir.clone(),
+        dir,
+        drafts: context.drafts,
+        symlinks: context.symlinks,
+        options: context.options.clone(),
+    };
+    let access = context.access.clone();
+    let reader = identity.clone();
+    let body = tokio::task::spawn_blocking(move || {
+        book::render(&book, |url| auth::allowed(&access, url, reader.as_ref()))
+    })
+    .await
+    .map_err(|_| warp::reject())?;
+
+    let mut response = generated(&context, body);
+    if identity.is_some() {
+        response.headers_mut().insert(
+            http::header::CACHE_CONTROL,
+            http::HeaderValue::from_static("private, no-cache"),
+        );
+    }
+    Ok(response)
+}
+
+async fn export(
+    context: &Context,
+    headers: &http::HeaderMap,
+    identity: Option<&auth::Identity>,
+    negotiated: &lang::Negotiated,
+    page: &Page,
+    format: pandoc::Format,
+) -> Result<warp::reply::Response, Rejection> {
+    let pandoc = match context.pandoc.clone() {
+        Some(pandoc) => pandoc,
+        None => return Ok(not_found()),
+    };
+    let job = pandoc::Job {
+        pandoc,
+        source: negotiated.path.clone(),
+        format,
+        title: match page.front_matter.get_str("title") {
+            Some(_) => None,
+            None => Some(page.title.clone()),
+        },
+    };
+    let stem = negotiated
+        .path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let owner = context.job_owner(identity);
+    let submitted = context.jobs.submit(owner.clone(), move || {
+        pandoc::run(&job).map(|bytes| jobs::Output {
+            bytes,
+            mime: format.mime(),
+            filename: format!("{}.{}", stem, format.extension()),
+        })
+    });
+    Ok(queued(context, headers, owner.as_deref(), submitted))
+}
+
+async fn redirect_map(
+    path: warp::filters::path::FullPath,
+    query: HashMap<String, String>,
+    context: Context,
+) -> Result<warp::reply::Response, Rejection> {
+    match context.redirects.find(path.as_str()) {
+        Some((status, to)) => Ok(redirect(status, &with_query(&to, &query))),
+        None => Err(warp::reject::not_found()),
+    }
+}
+
+async fn convert(
+    path: warp::filters::path::FullPath,
+    query: HashMap<String, String>,
+    headers: http::HeaderMap,
+    context: Context,
+) -> Result<warp::reply::Response, Rejection> {
+    use warp::Reply;
+
+    let path = match index::claim(
+        &context.base_dir,
+        context.symlinks,
+        &context.index,
+        path.as_str(),
+    )
+    .await
+    {
+        Some(index::Claim::Moved(to)) => {
+            return Ok(redirect(
+                http::StatusCode::MOVED_PERMANENTLY,
+                &with_query(&to, &query),
+            ))
+        }
+        Some(index::Claim::Serve(file_url)) => file_url,
+        None => String::from(path.as_str()),
+    };
+    if context.book.is_none() {
+        if let Some(location) = canonical(&context.base_dir, &path) {
+            return Ok(redirect(
+                http::StatusCode::MOVED_PERMANENTLY,
+                &with_query(&location, &query),
+            ));
+        }
+    }
+    if query.get("archive").map(|a| a == "zip").unwrap_or(false) {
+        return zip_archive(&path, &query, &headers, &context).await;
+    }
+    if query.contains_key("book") {
+        return book_view(&path, &headers, &context).await;
+    }
+    if context.book.is_none() && path.ends_with('/') && !has_index(&context.base_dir, &path) {
+        return directory_listing(&path, &query, &headers, &context).await;
+    }
+
+    let Found {
+        context,
+        identity,
+        url,
+        negotiated,
+        page,
+    } = match lookup(&path, &query, &headers, &context).await? {
+        Lookup::Found(found) => found,
+        Lookup::Refused(response) => return Ok(response),
+    };
+    if let Some(format) = query.get("format").and_then(|f| pandoc::Format::parse(f)) {
+        return export(
+            &context,
+            &headers,
+            identity.as_ref(),
+            &negotiated,
+            &page,
+            format,
+        )
+        .await;
+    }
+    if query.contains_key("slides") {
+        let source = encoding::read_to_string(&negotiated.path).map_err(|_| warp::reject())?;
+        let (front_matter, body) = frontmatter::split(&source);
+        let deck = slides::page(&page.title, body, &context.options.for_page(&front_matter));
+        let mut response = warp::reply::html(deck).into_response();
+        response.headers_mut().insert(
+            http::header::CACHE_CONTROL,
+            if page.is_protected() || identity.is_some() {
+                http::HeaderValue::from_static("private, no-cache")
+            } else {
+                context.page_cache.clone()
+            },
+        );
+        return Ok(response);
+    }
+    let wants_source = header_str(&headers, http::header::ACCEPT)
+        .map(|a| a.contains("text/markdown"))
+        .unwrap_or(false);
+    if wants_source {
+        // decoded as for rendering, so the charset said is the one sent
+        let source = encoding::read_to_string(&negotiated.path).map_err(|_| warp::reject())?;
+        let mut response = warp::reply::Response::new(source.into());
+        let headers = response.headers_mut();
+        headers.insert(
+            http::header::CONTENT_TYPE,
+            http::HeaderValue::from_static("text/markdown; charset=UTF-8"),
+        );
+        headers.insert(
+            http::header::VARY,
+            http::HeaderValue::from_static("accept, accept-language"),
+        );
+        headers.insert(
+            http::header::CACHE_CONTROL,
+            if page.is_protected() {
+                http::HeaderValue::from_static("private, no-cache")
+            } else {
+                context.page_cache.clone()
+            },
+        );
+        return Ok(response);
+    }
+    let entries = visible(&context, identity.as_ref()).await;
+    let backlinks = links::section(&url, &entries);
+    let recent = context
+        .recent_widget
+        .map(|limit| recent::widget(&entries, limit))
+        .unwrap_or_default();
+    let summary = context
+        .book
+        .as_ref()
+        .map(|book| mdbook::sidebar(book, path.as_str()))
+        .unwrap_or_default();
+    let header = partial(&context, &negotiated.path, "_header.md")
+        .await
+        .map(|html| format!("<div class=\"section-header\">\n{}</div>\n", html))
+        .unwrap_or_default();
+    let footer = partial(&context, &negotiated.path, "_footer.md")
+        .await
+        .map(|html| format!("<div class=\"section-footer\">\n{}</div>\n", html))
+        .unwrap_or_default();
+    let comments = if page.front_matter.get_bool("comments").unwrap_or(true) {
+        comments::widget(&context.comments, &url)
+    } else {
+        String::new()
+    };
+    let last_modified = if page.front_matter.get_bool("last_modified").unwrap_or(true) {
+        last_modified::footer(
+            &context.last_modified,
+            page.committed.unwrap_or(page.modified),
+            &negotiated.lang,
+        )
+    } else {
+        String::new()
+    };
+    let switcher = if context.options.quick_switcher {
+        switcher::widget()
+    } else {
+        String::new()
+    };
+    let margin_notes = if context.options.annotations && identity.is_some() {
+        annotations::widget(&url)
+    } else {
+        String::new()
+    };
+    let session = identity
+        .as_ref()
+        .map(|identity| {
+            format!(
+                "<div class=\"session\">{}</div>\n",
+                listing::escape(&identity.user)
+            )
+        })
+        .unwrap_or_default();
+    let rendered = Rendered {
+        body: session
+            + &summary
+            + &lang::switcher(&negotiated.lang, &negotiated.available)
+            + &header
+            + &page.html
+            + &context.options.element_scripts(&page.html)
+            + &footer
+            + &last_modified
+            + &comments
+            + &backlinks
+            + &recent
+            + &switcher
+            + &margin_notes,
+        lang: negotiated.lang,
+        templates: page
+            .front_matter
+            .get_str("template")
+            .and_then(|name| context.templates.named(name))
+            .unwrap_or_else(|| context.templates.clone()),
+        cache_control: if page.is_protected() || identity.is_some() {
+            http::HeaderValue::from_static("private, no-cache")
+        } else {
+            context.page_cache.clone()
+        },
+        meta: indexing::meta(&page.front_matter),
+        analytics: analytics::snippet(&context.analytics, context.host.as_deref()),
+        vars: template::Vars {
+            words: Some(page.words),
+            attachments: template::attachments(&page.attachments),
+        },
+    };
+    if query
+        .get("format")
+        .map(|f| f == "html-standalone")
+        .unwrap_or(false)
+    {
+        let page_dir = negotiated.path.parent().unwrap_or(&context.base_dir);
+        let html = standalone::inline(
+            &rendered.html(),
+            &standalone::Files {
+                base_dir: &context.base_dir,
+                theme_dir: context.theme_dir.as_deref(),
+                page_dir,
+            },
+        );
+        let stem = negotiated
+            .path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut response = warp::reply::Response::new(html.into());
+        let headers = response.headers_mut();
+        headers.insert(
+            http::header::CONTENT_TYPE,
+            http::HeaderValue::from_static("text/html; charset=UTF-8"),
+        );
+        if let Ok(disposition) =
+            http::HeaderValue::from_str(&format!("attachment; filename=\"{}.html\"", stem))
+        {
+            headers.insert(http::header::CONTENT_DISPOSITION, disposition);
+        }
+        return Ok(response);
+    }
+    Ok(etag::conditional(
+        header_str(&headers, http::header::IF_NONE_MATCH),
+        rendered.into_response(),
+    ))
+}
+
+async fn api_page(
+    tail: warp::filters::path::Tail,
+    query: HashMap<String, String>,
+    headers: http::HeaderMap,
+    context: Context,
+) -> Result<warp::reply::Response, Rejection> {
+    use warp::Reply;
+
+    let req_path = format!("/{}", tail.as_str());
+    let (url, page) = match lookup(&req_path, &query, &headers, &context).await {
+        Ok(Lookup::Found(found)) => (found.url, found.page),
+        Ok(Lookup::Refused(response)) => return Ok(response),
+        Err(_) => return Ok(not_found()),
+    };
+    // where each one is in the site, and whether it's there, for link checkers
+    let attachments: Vec<serde_json::Value> = page
+        .attachments
+        .iter()
+        .map(|attachment| {
+            let path = links::resolve(&url, &attachment.url);
+            let exists = path.as_ref().map_or(false, |p| {
+                let decoded = percent_encoding::percent_decode_str(p).decode_utf8_lossy();
+                context
+                    .base_dir
+                    .join(decoded.trim_start_matches('/'))
+                    .is_file()
+            });
+            serde_json::json!({
+                "kind": attachment.kind,
+                "url": attachment.url,
+                "path": path,
+                "exists": exists,
+            })
+        })
+        .collect();
+    let body = serde_json::json!({
+        "html": page.html,
+        "title": page.title,
+        "toc": page.toc,
+        "frontmatter": page.front_matter,
+        "last_modified": chrono::DateTime::<chrono::Utc>::from(page.modified).to_rfc3339(),
+        "etag": page.etag,
+        "words": page.words,
+        "reading_time": reading::minutes(page.words),
+        "attachments": attachments,
+    });
+    let mut response = warp::reply::json(&body).into_response();
+    if let Ok(tag) = http::HeaderValue::from_str(&page.etag) {
+        response.headers_mut().insert(http::header::ETAG, tag);
+    }
+    Ok(etag::conditional(
+        header_str(&headers, http::header::IF_NONE_MATCH),
+        response,
+    ))
+}
+
+async fn api_tree(
+    headers: http::HeaderMap,
+    context: Context,
+) -> Result<warp::reply::Response, Rejection> {
+    use warp::Reply;
+
+    let identity = context.identity(&headers);
+    let entries = visible(&context, identity.as_ref()).await;
+    Ok(personal(
+        warp::reply::json(&tree::build(&entries)).into_response(),
+        identity.as_ref(),
+    ))
+}
+
+/// Document changes as server-sent events, those of paths the reader
+/// may not see left out.
+async fn event_stream(
+    headers: http::HeaderMap,
+    context: Context,
+) -> Result<warp::reply::Response, Rejection> {
+    use futures::StreamExt;
+    use warp::Reply;
+
+    let identity = context.identity(&headers);
+    let access = context.access.clone();
+    let changes = context.events.subscribe().filter_map(move |change| {
+        let event = match change {
+            Ok(change)
+                if auth::allowed(&access, &change.url, identity.as_ref())
+                    && auth::authorize(&change.front_matter, identity.as_ref(), None) =>
+            {
+                serde_json::to_string(&change).ok().map(|data| {
+                    Ok::<_, ::std::convert::Infallible>((
+                        warp::sse::event(change.kind),
+                        warp::sse::data(data),
+                    ))
+                })
+            }
+            // lagging behind, or a page off limits as `visible` has it
+            _ => None,
+        };
+        futures::future::ready(event)
+    });
+    Ok(warp::sse::reply(warp::sse::keep_alive().stream(changes)).into_response())
+}
+
+/// What changed in a page between two git revisions, `to` being the file
+/// as it is now unless given.
+async fn diff_page(
+    tail: warp::filters::path::Tail,
+    query: HashMap<String, String>,
+    headers: http::HeaderMap,
+    context: Context,
+) -> Result<warp::reply::Response, Rejection> {
+    let from = String::from(query.get("from").map(String::as_str).unwrap_or("HEAD"));
+    let to = query.get("to").cloned();
+    if !diff::valid_rev(&from) || !to.as_deref().map(diff::valid_rev).unwrap_or(true) {
+        return Ok(bad_request(String::from("from and to want git revisions")));
+    }
+    let req_path = format!("/{}", tail.as_str());
+    let Found {
+        context,
+        identity,
+        negotiated,
+        page,
+        ..
+    } = match lookup(&req_path, &query, &headers, &context).await? {
+        Lookup::Found(found) => found,
+        Lookup::Refused(response) => return Ok(response),
+    };
+
+    let path = negotiated.path.clone();
+    let options = context.options.clone();
+    let revs = (from.clone(), to.clone());
+    let compared = tokio::task::spawn_blocking(move || {
+        let old = diff::at_rev(&path, &revs.0)?;
+        let new = match revs.1.as_ref() {
+            Some(rev) => diff::at_rev(&path, rev)?,
+            None => encoding::read_to_string(&path).map_err(|e| e.to_string())?,
+        };
+        let html = |input: &str| {
+            let (front_matter, body) = frontmatter::split(input);
+            render::render(body, &options.for_page(&front_matter)).0
+        };
+        Ok::<String, String>(diff::words(&html(&old), &html(&new)))
+    })
+    .await
+    .map_err(|_| warp::reject())?;
+    let compared = match compared {
+        Ok(compared) => compared,
+        Err(err) => return Ok(bad_request(err)),
+    };
+
+    let body = format!(
+        "<h1>{}</h1>\n<p class=\"diff-revs\">{} → {}</p>\n<div class=\"diff\">\n{}</div>\n",
+        listing::escape(&page.title),
+        listing::escape(&from),
+        listing::escape(to.as_deref().unwrap_or("working copy")),
+        compared
+    );
+    let mut response = generated(&context, body);
+    if page.is_protected() || identity.is_some() {
+        response.headers_mut().insert(
+            http::header::CACHE_CONTROL,
+            http::HeaderValue::from_static("private, no-cache"),
+        );
+    }
+    Ok(response)
+}
+
+async fn stats_page(
+    query: HashMap<String, String>,
+    headers: http::HeaderMap,
+    context: Context,
+) -> Result<warp::reply::Response, Rejection> {
+    use warp::Reply;
+
+    match context.identity(&headers) {
+        Some(identity) if identity.in_group("admin") => {}
+        Some(_) => return Ok(forbidden()),
+        None => return Ok(unauthorized()),
+    }
+    let (entries, memory) = {
+        let cache = context.cache.lock().await;
+        let memory = cache
+            .iter()
+            .map(|(key, page)| {
+                key.path.as_os_str().len()
+                    + page.html.len()
+                    + page.title.len()
+                    + page.etag.len()
+                    + page
+                        .toc
+                        .iter()
+                        .map(|h| h.text.len() + h.id.len())
+                        .sum::<usize>()
+            })
+            .sum();
+        (cache.len(), memory)
+    };
+    let snapshot = context.stats.snapshot(&context.base_dir, entries, memory);
+    let wants_json = query.get("format").map(|f| f == "json").unwrap_or(false)
+        || header_str(&headers, http::header::ACCEPT)
+            .map(|a| a.contains("application/json"))
+            .unwrap_or(false);
+    if wants_json {
+        Ok(warp::reply::json(&snapshot).into_response())
+    } else {
+        Ok(generated(&context, stats::page(&snapshot)))
+    }
+}
+
+async fn site_toc(
+    headers: http::HeaderMap,
+    context: Context,
+) -> Result<warp::reply::Response, Rejection> {
+    let identity = context.identity(&headers);
+    let entries = visible(&context, identity.as_ref()).await;
+    let body = tree::page(&tree::build(&entries));
+    Ok(personal(generated(&context, body), identity.as_ref()))
+}
+
+async fn search_page(
+    query: HashMap<String, String>,
+    headers: http::HeaderMap,
+    context: Context,
+) -> Result<warp::reply::Response, Rejection> {
+    let q = query.get("q").map(String::as_str).unwrap_or("");
+    let identity = context.identity(&headers);
+    let entries = visible(&context, identity.as_ref()).await;
+    let results = search::search(&entries, q);
+    let body = search::page(q, &results);
+    Ok(personal(generated(&context, body), identity.as_ref()))
+}
+
+/// Scheme and host the client reached us at, for absolute URLs.
+fn origin(headers: &http::HeaderMap) -> String {
+    let host = header_str(headers, http::header::HOST).unwrap_or("localhost");
+    let scheme = headers
+        .get("x-forwarded-proto")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("http");
+    format!("{}://{}", scheme, host)
+}
+
+async fn opensearch(headers: http::HeaderMap) -> Result<warp::reply::Response, Rejection> {
+    let origin = origin(&headers);
+    let mut response = warp::reply::Response::new(search::opensearch(&origin).into());
+    response.headers_mut().insert(
+        http::header::CONTENT_TYPE,
+        http::HeaderValue::from_static("application/opensearchdescription+xml"),
+    );
+    Ok(response)
+}
+
+/// The published pages search engines may index, pages anyone may read.
+async fn sitemap(
+    headers: http::HeaderMap,
+    context: Context,
+) -> Result<warp::reply::Response, Rejection> {
+    let entries = visible(&context, None).await;
+    let mut response =
+        warp::reply::Response::new(indexing::sitemap(&origin(&headers), &entries).into());
+    response.headers_mut().insert(
+        http::header::CONTENT_TYPE,
+        http::HeaderValue::from_static("application/xml"),
+    );
+    Ok(response)
+}
+
+/// The link graph of the pages the reader may see, drawn or as JSON or DOT.
+async fn graph_page(
+    query: HashMap<String, String>,
+    headers: http::HeaderMap,
+    context: Context,
+) -> Result<warp::reply::Response, Rejection> {
+    use warp::Reply;
+
+    let identity = context.identity(&headers);
+    let entries = visible(&context, identity.as_ref()).await;
+    let response = match query.get("format").map(String::as_str) {
+        Some("json") => warp::reply::json(&graph::build(&entries)).into_response(),
+        Some("dot") => {
+            let mut response =
+                warp::reply::Response::new(graph::dot(&graph::build(&entries)).into());
+            response.headers_mut().insert(
+                http::header::CONTENT_TYPE,
+                http::HeaderValue::from_static("text/vnd.graphviz; charset=UTF-8"),
+            );
+            response
+        }
+        _ => generated(&context, graph::page()),
+    };
+    Ok(personal(response, identity.as_ref()))
+}
+
+/// From the index the tree's watcher keeps, rather than walking it again.
+async fn published(context: &Context) -> Vec<index::Entry> {
+    index::published(
+        index::entries(&context.base_dir, context.symlinks, &context.index).await,
+        context.drafts,
+    )
+}
+
+/// The published pages `identity` may read, for everything listing pages:
+/// those the `[[access]]` rules and their own `protected` let through.
+/// Pages behind a `password` are never listed.
+async fn visible(context: &Context, identity: Option<&auth::Identity>) -> Vec<index::Entry> {
+    published(context)
+        .await
+        .into_iter()
+        .filter(|entry| {
+            auth::allowed(&context.access, &entry.url(), identity)
+                && auth::authorize(&entry.front_matter, identity, None)
+        })
+        .collect()
+}
+
+/// Marks a response listing pages for `identity` as theirs alone.
+fn personal(
+    mut response: warp::reply::Response,
+    identity: Option<&auth::Identity>,
+) -> warp::reply::Response {
+    if identity.is_some() {
+        response.headers_mut().insert(
+            http::header::CACHE_CONTROL,
+            http::HeaderValue::from_static("private, no-cache"),
+        );
+    }
+    response
+}
+
+fn generated(context: &Context, body: String) -> warp::reply::Response {
+    use warp::Reply;
+    Rendered {
+        body,
+        lang: context.default_lang.clone(),
+        templates: context.templates.clone(),
+        cache_control: context.page_cache.clone(),
+        meta: String::new(),
+        analytics: analytics::snippet(&context.analytics, context.host.as_deref()),
+        vars: template::Vars::default(),
+    }
+    .into_response()
+}
+
+async fn tag_index(
+    headers: http::HeaderMap,
+    context: Context,
+) -> Result<warp::reply::Response, Rejection> {
+    let identity = context.identity(&headers);
+    let entries = visible(&context, identity.as_ref()).await;
+    let body = tags::index_page(&entries);
+    Ok(personal(generated(&context, body), identity.as_ref()))
+}
+
+async fn tag_listing(
+    tag: String,
+    headers: http::HeaderMap,
+    context: Context,
+) -> Result<warp::reply::Response, Rejection> {
+    let identity = context.identity(&headers);
+    let entries = visible(&context, identity.as_ref()).await;
+    match tags::tag_page(&tags::decode(&tag), &entries) {
+        Some(body) => Ok(personal(generated(&context, body), identity.as_ref())),
+        None => Ok(not_found()),
+    }
+}
+
+async fn recent_page(
+    query: HashMap<String, String>,
+    headers: http::HeaderMap,
+    context: Context,
+) -> Result<warp::reply::Response, Rejection> {
+    let limit = query
+        .get("limit")
+        .and_then(|l| l.parse().ok())
+        .unwrap_or(recent::DEFAULT_LIMIT);
+    let identity = context.identity(&headers);
+    let entries = visible(&context, identity.as_ref()).await;
+    let body = recent::page(&entries, limit);
+    Ok(personal(generated(&context, body), identity.as_ref()))
+}
+
+async fn guard_static(
+    path: warp::filters::path::FullPath,
+    headers: http::HeaderMap,
+    context: Context,
+) -> Result<(), Rejection> {
+    // warp::fs decodes the path before opening it, so check the decoded one
+    let decoded = percent_encoding::percent_decode_str(path.as_str())
+        .decode_utf8_lossy()
+        .into_owned();
+    let path = Path::new(&decoded);
+    let settings = path.file_name().map_or(false, |n| {
+        n == overrides::FILE_NAME || annotations::is_sidecar(&n.to_string_lossy())
+    });
+    let linked = !symlinks::allowed(
+        context.symlinks,
+        &context.base_dir,
+        &context.base_dir.join(decoded.trim_start_matches('/')),
+    );
+    // identifying may cost a bcrypt round, only do it for guarded paths
+    let guarded = context
+        .access
+        .iter()
+        .any(|rule| decoded.starts_with(&rule.prefix));
+    let refused = guarded
+        && !auth::allowed(
+            &context.access,
+            &decoded,
+            context.identity(&headers).as_ref(),
+        );
+    if settings || linked || refused || (!context.drafts && is_draft_path(path)) {
+        Err(warp::reject::not_found())
+    } else {
+        Ok(())
+    }
+}
+
+async fn upload(
+    headers: http::HeaderMap,
+    form: warp::multipart::FormData,
+    context: Context,
+) -> Result<warp::reply::Response, Rejection> {
+    use futures::TryStreamExt;
+    use warp::{Buf, Reply};
+
+    if !context.edit {
+        return Ok(not_found());
+    }
+    if context.identity(&headers).is_none() {
+        return Ok(unauthorized());
+    }
+
+    let dir = context.base_dir.join(&context.upload_dir);
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .map_err(|_| warp::reject())?;
+
+    let mut uploaded = Vec::new();
+    let mut parts = form;
+    while let Some(mut part) = parts.try_next().await.map_err(|_| warp::reject())? {
+        let name = match part.filename() {
+            Some(filename) => upload::sanitize(filename),
+            None => continue,
+        };
+        let mut content: Vec<u8> = Vec::new();
+        while let Some(chunk) = part.data().await {
+            let chunk = chunk.map_err(|_| warp::reject())?;
+            content.extend_from_slice(chunk.bytes());
+        }
+        let path = upload::unique_path(&dir, &name);
+        tokio::fs::write(&path, content)
+            .await
+            .map_err(|_| warp::reject())?;
+        let rel = path
+            .strip_prefix(&context.base_dir)
+            .unwrap_or(&path)
+            .to_string_lossy()
+            .replace('\\', "/");
+        let url = format!("/{}", rel);
+        uploaded.push(serde_json::json!({
+            "url": url,
+            "markdown": upload::snippet(&name, &url),
+        }));
+    }
+    Ok(warp::reply::json(&uploaded).into_response())
+}
+
+/// A page's annotations, for signed in readers who may read the page.
+async fn annotations_list(
+    tail: warp::filters::path::Tail,
+    query: HashMap<String, String>,
+    headers: http::HeaderMap,
+    context: Context,
+) -> Result<warp::reply::Response, Rejection> {
+    use warp::Reply;
+
+    if !context.options.annotations {
+        return Ok(not_found());
+    }
+    let req_path = format!("/{}", tail.as_str());
+    let Found {
+        identity,
+        negotiated,
+        ..
+    } = match lookup(&req_path, &query, &headers, &context).await? {
+        Lookup::Found(found) => found,
+        Lookup::Refused(response) => return Ok(response),
+    };
+    if identity.is_none() {
+        return Ok(unauthorized());
+    }
+    let mut response = warp::reply::json(&annotations::load(&negotiated.path)).into_response();
+    response.headers_mut().insert(
+        http::header::CACHE_CONTROL,
+        http::HeaderValue::from_static("private, no-cache"),
+    );
+    Ok(response)
+}
+
+/// Adds an annotation to a page, or with `?resolve=<id>` resolves one.
+async fn annotate(
+    tail: warp::filters::path::Tail,
+    query: HashMap<String, String>,
+    headers: http::HeaderMap,
+    body: serde_json::Value,
+    context: Context,
+) -> Result<warp::reply::Response, Rejection> {
+    use warp::Reply;
+
+    if !context.options.annotations {
+        return Ok(not_found());
+    }
+    let req_path = format!("/{}", tail.as_str());
+    let Found {
+        identity,
+        negotiated,
+        ..
+    } = match lookup(&req_path, &query, &headers, &context).await? {
+        Lookup::Found(found) => found,
+        Lookup::Refused(response) => return Ok(response),
+    };
+    let user = match identity {
+        Some(identity) => identity.user,
+        None => return Ok(unauthorized()),
+    };
+    let page = negotiated.path.clone();
+    let changed = match query.get("resolve").cloned() {
+        Some(id) => {
+            match tokio::task::spawn_blocking(move || annotations::resolve(&page, &id, &user))
+                .await
+                .map_err(|_| warp::reject())?
+            {
+                Ok(Some(annotation)) => annotation,
+                Ok(None) => return Ok(not_found()),
+                Err(err) => return Ok(server_error(err)),
+            }
+        }
+        None => {
+            let new: annotations::New = match serde_json::from_value(body) {
+                Ok(new) => new,
+                Err(err) => return Ok(bad_request(err.to_string())),
+            };
+            match tokio::task::spawn_blocking(move || annotations::add(&page, &user, new))
+                .await
+                .map_err(|_| warp::reject())?
+            {
+                Ok(annotation) => annotation,
+                Err(err) => return Ok(bad_request(err)),
+            }
+        }
+    };
+    Ok(warp::reply::json(&changed).into_response())
+}
+
+fn static_headers(
+    path: warp::filters::path::FullPath,
+    context: Context,
+    file: warp::filters::fs::File,
+) -> warp::reply::Response {
+    use warp::Reply;
+
+    let mut response = file.into_response();
+    if response.status().is_success() {
+        context
+            .static_cache
+            .apply(path.as_str(), response.headers_mut());
+    }
+    response
+}
+
+async fn admin_reload(
+    headers: http::HeaderMap,
+    context: Context,
+) -> Result<warp::reply::Response, Rejection> {
+    use warp::Reply;
+
+    match context.identity(&headers) {
+        Some(identity) if identity.in_group("admin") => {}
+        Some(_) => return Ok(forbidden()),
+        None => return Ok(unauthorized()),
+    }
+    match context.reload().await {
+        Ok(()) => Ok(warp::reply::json(&serde_json::json!({ "reloaded": true })).into_response()),
+        Err(err) => Ok(server_error(err)),
+    }
+}
+
+/// Pages whose front matter doesn't fit the configured schema.
+async fn admin_schema(
+    query: HashMap<String, String>,
+    headers: http::HeaderMap,
+    context: Context,
+) -> Result<warp::reply::Response, Rejection> {
+    use warp::Reply;
+
+    match context.identity(&headers) {
+        Some(identity) if identity.in_group("admin") => {}
+        Some(_) => return Ok(forbidden()),
+        None => return Ok(unauthorized()),
+    }
+    let entries = published(&context).await;
+    let violations: Vec<schema::Violation> = entries
+        .iter()
+        .flat_map(|entry| {
+            schema::validate(&context.schema, &entry.front_matter)
+                .into_iter()
+                .map(move |message| schema::Violation {
+                    path: entry.path.to_string_lossy().replace('\\', "/"),
+                    url: entry.url(),
+                    message,
+                })
+        })
+        .collect();
+    let mut response = if query.get("format").map(|f| f == "json").unwrap_or(false) {
+        warp::reply::json(&violations).into_response()
+    } else {
+        generated(&context, schema::page(&violations))
+    };
+    response.headers_mut().insert(
+        http::header::CACHE_CONTROL,
+        http::HeaderValue::from_static("private, no-cache"),
+    );
+    Ok(response)
+}
+
+#[cfg(unix)]
+async fn reload_on_hangup(context: Context) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(err) => {
+            eprintln!("no SIGHUP handler: {}", err);
+            return;
+        }
+    };
+    while hangups.recv().await.is_some() {
+        match context.reload().await {
+            Ok(()) => eprintln!("configuration reloaded"),
+            Err(err) => eprintln!("configuration not reloaded: {}", err),
+        }
+    }
+}
+
+fn inject_context(ctx: Context) -> warp::filters::BoxedFilter<(Context,)> {
+    warp::header::optional::<String>("host")
+        .and(warp::ext::get::<server::Remote>())
+        .map(
+            move |host: Option<String>, remote: server::Remote| Context {
+                remote: Some(remote.0.ip()),
+                host: host.clone(),
+                ..ctx.for_host(host.as_deref()).current()
+            },
+        )
+        .boxed()
+}
+
+/// Counts what a response sends, in the stats of the tree it comes from.
+fn account(
+    path: warp::filters::path::FullPath,
+    context: Context,
+    reply: impl warp::Reply,
+) -> warp::reply::Response {
+    let response = reply.into_response();
+    context.stats.sent(
+        path.as_str(),
+        response.status(),
+        stats::body_size(&response),
+    );
+    response
+}
+
+fn static_dir(
+    base_dir: PathBuf,
+    ctx: &Context,
+) -> warp::filters::BoxedFilter<(warp::reply::Response,)> {
+    warp::path::full()
+        .and(warp::header::headers_cloned())
+        .and(inject_context(ctx.clone()))
+        .and_then(guard_static)
+        .untuple_one()
+        .and(warp::path::full())
+        .and(inject_context(ctx.clone()))
+        .and(warp::fs::dir(base_dir))
+        .map(static_headers)
+        .boxed()
+}
+
+fn on_host(name: String) -> warp::filters::BoxedFilter<()> {
+    warp::header::optional::<String>("host")
+        .and_then(move |host: Option<String>| {
+            let matching = host
+                .map(|h| host_name(&h).eq_ignore_ascii_case(&name))
+                .unwrap_or(false);
+            async move {
+                if matching {
+                    Ok(())
+                } else {
+                    Err(warp::reject::not_found())
+                }
+            }
+        })
+        .untuple_one()
+        .boxed()
+}
+
+// #[tokio::main]
+async fn serve(
+    ctx: Context,
+    addr: std::net::SocketAddr,
+    tuning: config::Server,
+    open: bool,
+    json: bool,
+) {
+    let mut dir = static_dir(ctx.base_dir.clone(), &ctx);
+    for (name, vhost) in ctx.vhosts.iter() {
+        dir = on_host(name.clone())
+            .and(static_dir(vhost.base_dir.clone(), &ctx))
+            .or(dir)
+            .map(|file| warp::Reply::into_response(file))
+            .boxed();
+    }
+    let theme = match ctx.theme_dir.clone() {
+        Some(theme_dir) => warp::path("_theme")
+            .and(warp::query::<HashMap<String, String>>())
+            .and(warp::fs::dir(theme_dir.join("assets")))
+            .map(
+                |query: HashMap<String, String>, file: warp::filters::fs::File| {
+                    let mut response = warp::Reply::into_response(file);
+                    // the token in the URL changes along with the file
+                    if query.contains_key("v") && response.status().is_success() {
+                        response.headers_mut().insert(
+                            http::header::CACHE_CONTROL,
+                            http::HeaderValue::from_static("public, max-age=31536000, immutable"),
+                        );
+                    }
+                    response
+                },
+            )
+            .boxed(),
+        None => warp::path("_theme")
+            .and_then(|| async {
+                Err::<warp::reply::Response, Rejection>(warp::reject::not_found())
+            })
+            .boxed(),
+    };
+    let tag_index = warp::path("_tags")
+        .and(warp::path::end())
+        .and(warp::header::headers_cloned())
+        .and(inject_context(ctx.clone()))
+        .and_then(tag_index);
+    let tag_listing = warp::path("_tags")
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::header::headers_cloned())
+        .and(inject_context(ctx.clone()))
+        .and_then(tag_listing);
+    let recent = warp::path("_recent")
+        .and(warp::path::end())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(warp::header::headers_cloned())
+        .and(inject_context(ctx.clone()))
+        .and_then(recent_page);
+    let api_page = warp::path("_api")
+        .and(warp::path("page"))
+        .and(warp::path::tail())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(warp::header::headers_cloned())
+        .and(inject_context(ctx.clone()))
+        .and_then(api_page);
+    let api_tree = warp::path("_api")
+        .and(warp::path("tree"))
+        .and(warp::path::end())
+        .and(warp::header::headers_cloned())
+        .and(inject_context(ctx.clone()))
+        .and_then(api_tree);
+    let stats = warp::path("_stats")
+        .and(warp::path::end())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(warp::header::headers_cloned())
+        .and(inject_context(ctx.clone()))
+        .and_then(stats_page);
+    let job = warp::path("_jobs")
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(warp::header::headers_cloned())
+        .and(inject_context(ctx.clone()))
+        .and_then(job_status);
+    let schema_report = warp::path("_admin")
+        .and(warp::path("schema"))
+        .and(warp::path::end())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(warp::header::headers_cloned())
+        .and(inject_context(ctx.clone()))
+        .and_then(admin_schema);
+    let annotations_list = warp::path("_annotations")
+        .and(warp::path::tail())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(warp::header::headers_cloned())
+        .and(inject_context(ctx.clone()))
+        .and_then(annotations_list);
+    let changes = warp::path("_events")
+        .and(warp::path::end())
+        .and(warp::header::headers_cloned())
+        .and(inject_context(ctx.clone()))
+        .and_then(event_stream);
+    let diff = warp::path("_diff")
+        .and(warp::path::tail())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(warp::header::headers_cloned())
+        .and(inject_context(ctx.clone()))
+        .and_then(diff_page);
+    let site_toc = warp::path("_toc")
+        .and(warp::path::end())
+        .and(warp::header::headers_cloned())
+        .and(inject_context(ctx.clone()))
+        .and_then(site_toc);
+    let link_graph = warp::path("_graph")
+        .and(warp::path::end())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(warp::header::headers_cloned())
+        .and(inject_context(ctx.clone()))
+        .and_then(graph_page);
+    let search = warp::path("_search")
+        .and(warp::path::end())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(warp::header::headers_cloned())
+        .and(inject_context(ctx.clone()))
+        .and_then(search_page);
+    let sitemap = warp::path("sitemap.xml")
+        .and(warp::path::end())
+        .and(warp::header::headers_cloned())
+        .and(inject_context(ctx.clone()))
+        .and_then(sitemap);
+    let opensearch = warp::path("opensearch.xml")
+        .and(warp::path::end())
+        .and(warp::header::headers_cloned())
+        .and_then(opensearch);
+    let pages = warp::path::full()
+        .and(warp::query::<HashMap<String, String>>())
+        .and(warp::header::headers_cloned())
+        .and(inject_context(ctx.clone()))
+        .and_then(convert);
+    let redirects = warp::path::full()
+        .and(warp::query::<HashMap<String, String>>())
+        .and(inject_context(ctx.clone()))
+        .and_then(redirect_map);
+    let get = warp::get().and(
+        redirects
+            .or(theme)
+            .or(tag_index)
+            .or(tag_listing)
+            .or(recent)
+            .or(api_page)
+            .or(api_tree)
+            .or(stats)
+            .or(schema_report)
+            .or(job)
+            .or(annotations_list)
+            .or(changes)
+            .or(diff)
+            .or(site_toc)
+            .or(link_graph)
+            .or(search)
+            .or(opensearch)
+            .or(sitemap)
+            .or(pages)
+            .or(dir),
+    );
+    let upload = warp::post()
+        .and(warp::path("_upload"))
+        .and(warp::path::end())
+        .and(warp::header::headers_cloned())
+        .and(warp::multipart::form().max_length(upload::MAX_SIZE))
+        .and(inject_context(ctx.clone()))
+        .and_then(upload);
+    let reload = warp::post()
+        .and(warp::path("_admin"))
+        .and(warp::path("reload"))
+        .and(warp::path::end())
+        .and(warp::header::headers_cloned())
+        .and(inject_context(ctx.clone()))
+        .and_then(admin_reload);
+    let annotate = warp::post()
+        .and(warp::path("_annotations"))
+        .and(warp::path::tail())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(warp::header::headers_cloned())
+        .and(warp::body::content_length_limit(16 * 1024))
+        .and(warp::body::json())
+        .and(inject_context(ctx.clone()))
+        .and_then(annotate);
+    #[cfg(unix)]
+    tokio::spawn(reload_on_hangup(ctx.clone()));
+    for context in ::std::iter::once(&ctx).chain(ctx.vhosts.values()) {
+        tokio::spawn(events::watch(
+            context.base_dir.clone(),
+            context.drafts,
+            context.symlinks,
+            context.index.clone(),
+            context.events.clone(),
+        ));
+    }
+    let routes = warp::path::full()
+        .and(inject_context(ctx.clone()))
+        .and(get.or(upload).or(reload).or(annotate))
+        .map(account)
+        .boxed();
+    // with port 0 the system picks one, what we print is where we really are
+    let (addr, server) = match server::bind(addr, &tuning, routes) {
+        Ok(bound) => bound,
+        Err(err) => {
+            eprintln!("cannot listen on {}: {}", addr, err);
+            ::std::process::exit(1);
+        }
+    };
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({
+                "address": addr.to_string(),
+                "url": browser::local_url(&addr),
+                "pid": ::std::process::id(),
+            })
+        );
+    } else {
+        println!("running on http://{}", addr);
+    }
+    if addr.ip().is_unspecified() && !json {
+        if let Some(ip) = browser::lan_ip() {
+            println!(
+                "on the network at http://{}",
+                std::net::SocketAddr::new(ip, addr.port())
+            );
+        }
+    }
+    if open {
+        if let Err(err) = browser::open(&browser::local_url(&addr)) {
+            eprintln!("{}", err);
+        }
+    }
+    server.await;
+}
+
+/// Identities given by a proxy or an OIDC provider, as set on the command line.
+fn upstream(matches: &clap::ArgMatches) -> auth::Upstream {
+    let proxy = matches.value_of("auth_header").map(|header| auth::Proxy {
+        user_header: String::from(header),
+        groups_header: matches.value_of("auth_groups_header").map(String::from),
+        trusted: matches
+            .values_of("trusted_proxy")
+            .into_iter()
+            .flatten()
+            .map(|cidr| auth::Cidr::parse(cidr).expect("not a valid address range"))
+            .collect(),
+    });
+    let bearer = matches.value_of("oidc_key").map(|key| {
+        auth::Bearer::new(
+            Path::new(key),
+            matches.value_of("oidc_issuer"),
+            matches.value_of("oidc_audience"),
+        )
+        .expect("could not read the OIDC key")
+    });
+    auth::Upstream { proxy, bearer }
+}
+
+/// A context serving `base_dir` with the same options as `ctx`, and its
+/// own cache, index and statistics.
+fn vhost(ctx: &Context, base_dir: PathBuf) -> Context {
+    let sources = settings::Sources {
+        base_dir: base_dir.clone(),
+        ..(*ctx.sources).clone()
+    };
+    let settings = settings::load(&sources).expect("could not load settings");
+    Context {
+        base_dir,
+        cache: ::std::sync::Arc::new(Mutex::new(HashMap::new())),
+        index: ::std::sync::Arc::new(Mutex::new(HashMap::new())),
+        book: None,
+        users: settings.users.clone(),
+        options: settings.options.clone(),
+        static_cache: settings.static_cache.clone(),
+        templates: settings.templates.clone(),
+        page_cache: settings.page_cache.clone(),
+        redirects: settings.redirects.clone(),
+        access: settings.access.clone(),
+        comments: settings.comments.clone(),
+        analytics: settings.analytics.clone(),
+        last_modified: settings.last_modified.clone(),
+        schema: settings.schema.clone(),
+        stats: ::std::sync::Arc::new(stats::Stats::default()),
+        events: ::std::sync::Arc::new(events::Events::default()),
+        jobs: ::std::sync::Arc::new(jobs::Jobs::default()),
+        sources: ::std::sync::Arc::new(sources),
+        settings: ::std::sync::Arc::new(::std::sync::RwLock::new(settings)),
+        ..ctx.clone()
+    }
+}
+
+fn symlink_policy(matches: &clap::ArgMatches) -> symlinks::Policy {
+    symlinks::Policy::parse(matches.value_of("follow_symlinks").unwrap_or("within-root"))
+        .expect("checked by clap")
+}
+
+fn lint_tree(matches: &clap::ArgMatches) -> i32 {
+    let base_dir = match matches.value_of("base_dir") {
+        Some(base_dir) => Path::new(base_dir),
+        None => {
+            eprintln!("lint needs a directory, given with --dir");
+            return 2;
+        }
+    };
+    let config = match matches
+        .value_of("config")
+        .map(|p| config::load(Path::new(p)))
+    {
+        Some(Ok(config)) => config,
+        Some(Err(err)) => {
+            eprintln!("{}", err);
+            return 2;
+        }
+        None => config::Config::default(),
+    };
+    let levels = match lint::Levels::new(&config.lint) {
+        Ok(levels) => levels,
+        Err(err) => {
+            eprintln!("{}", err);
+            return 2;
+        }
+    };
+    if let Err(err) = schema::check(&config.schema) {
+        eprintln!("{}", err);
+        return 2;
+    }
+    let problems = lint::run(base_dir, symlink_policy(matches), &levels, &config.schema);
+    print!(
+        "{}",
+        lint::report(&problems, matches.value_of("format").unwrap_or("text"))
+    );
+    if problems.iter().any(|p| p.level == lint::Level::Error) {
+        1
+    } else {
+        0
+    }
+}
+
+fn doctor_check(matches: &clap::ArgMatches, sub: &clap::ArgMatches) -> i32 {
+    let base_dir = match sub.value_of("base_dir") {
+        Some(base_dir) => PathBuf::from(base_dir),
+        None => {
+            eprintln!("doctor needs a directory, given with --dir");
+            return 2;
+        }
+    };
+    let checkup = doctor::Checkup {
+        sources: settings::Sources {
+            base_dir,
+            config: sub.value_of("config").map(PathBuf::from),
+            users: matches.value_of("users").map(PathBuf::from),
+            gfm: matches.is_present("gfm"),
+            no_smart: matches.is_present("no_smart"),
+            theme_dir: matches.value_of("theme_dir").map(PathBuf::from),
+            head: matches.value_of("head").map(PathBuf::from),
+            tail: matches.value_of("tail").map(PathBuf::from),
+        },
+        symlinks: symlink_policy(sub),
+        address: matches.value_of("address").map(String::from),
+        pandoc: matches.value_of("pandoc").map(PathBuf::from),
+        upload_dir: if matches.is_present("edit") {
+            Some(PathBuf::from(
+                matches.value_of("upload_dir").unwrap_or("uploads"),
+            ))
+        } else {
+            None
+        },
+    };
+    let found = doctor::run(&checkup);
+    print!("{}", doctor::report(&found));
+    if found.iter().any(|f| f.status == doctor::Status::Error) {
+        1
+    } else {
+        0
+    }
+}
+
+fn graph_tree(matches: &clap::ArgMatches, sub: &clap::ArgMatches) -> i32 {
+    let base_dir = match sub.value_of("base_dir") {
+        Some(base_dir) => PathBuf::from(base_dir),
+        None => {
+            eprintln!("graph needs a directory, given with --dir");
+            return 2;
+        }
+    };
+    let mut rt = match tokio::runtime::Runtime::new() {
+        Ok(rt) => rt,
+        Err(err) => {
+            eprintln!("{}", err);
+            return 2;
+        }
+    };
+    let index = ::std::sync::Arc::new(Mutex::new(HashMap::new()));
+    let entries = index::published(
+        rt.block_on(index::scan(&base_dir, symlink_policy(sub), &index)),
+        sub.is_present("drafts"),
+    );
+    let graph = graph::build(&entries);
+    match sub.value_of("format") {
+        Some("dot") => print!("{}", graph::dot(&graph)),
+        _ => println!(
+            "{}",
+            serde_json::to_string_pretty(&graph).expect("graphs serialize")
+        ),
+    }
+    0
+}
+
+fn build_site(matches: &clap::ArgMatches, sub: &clap::ArgMatches) -> i32 {
+    let base_dir = match sub.value_of("base_dir") {
+        Some(base_dir) => PathBuf::from(base_dir),
+        None => {
+            eprintln!("build needs a directory, given with --dir");
+            return 2;
+        }
+    };
+    let sources = settings::Sources {
+        base_dir: base_dir.clone(),
+        config: sub.value_of("config").map(PathBuf::from),
+        users: None,
+        gfm: matches.is_present("gfm"),
+        no_smart: matches.is_present("no_smart"),
+        theme_dir: matches.value_of("theme_dir").map(PathBuf::from),
+        head: matches.value_of("head").map(PathBuf::from),
+        tail: matches.value_of("tail").map(PathBuf::from),
+    };
+    let settings = match settings::load(&sources) {
+        Ok(settings) => settings,
+        Err(err) => {
+            eprintln!("{}", err);
+            return 2;
+        }
+    };
+    let build = build::Build {
+        base_dir,
+        out: PathBuf::from(sub.value_of("out").expect("required by clap")),
+        drafts: sub.is_present("drafts"),
+        symlinks: symlink_policy(sub),
+        options: settings.options,
+        templates: settings.templates,
+        theme_dir: sources.theme_dir.clone(),
+        schema: settings.schema,
+    };
+    let done = if let Some(page) = sub.value_of("single_file") {
+        build::single_file(&build, Path::new(page))
+            .map(|target| println!("{} written", target.display()))
+    } else if sub.is_present("watch") {
+        build::watch(&build)
+    } else {
+        build::run(&build).map(|written| println!("{} files written", written))
+    };
+    match done {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("{}", err);
+            1
+        }
+    }
+}
+
+fn app() -> App<'static, 'static> {
+    let base_dir = Arg::with_name("base_dir")
+        .short("d")
+        .long("dir")
+        .env("MDSERVE_DIR")
+        .value_name("base_dir")
+        .help("Directory to serve")
+        .global(true)
+        .takes_value(true);
+
+    let addr = Arg::with_name("address")
+        .short("a")
+        .long("address")
+        .env("MDSERVE_ADDRESS")
+        .value_name("address")
+        .help("address to listen to")
+        .takes_value(true);
+
+    let default_lang = Arg::with_name("default_lang")
+        .long("default-lang")
+        .env("MDSERVE_DEFAULT_LANG")
+        .value_name("lang")
+        .help("Language of documents without a language suffix")
+        .default_value("en")
+        .takes_value(true);
+
+    let drafts = Arg::with_name("drafts")
+        .long("drafts")
+        .help("Serve, export or graph draft pages too")
+        .global(true);
+
+    let recent_widget = Arg::with_name("recent_widget")
+        .long("recent-widget")
+        .value_name("count")
+        .help("Show the most recently updated documents on every page")
+        .takes_value(true);
+
+    let book = Arg::with_name("mdbook")
+        .long("mdbook")
+        .help("Preview the mdBook project found in base_dir");
+
+    let no_smart = Arg::with_name("no_smart")
+        .long("no-smart")
+        .help("Keep quotes and dashes as typed");
+
+    let head = Arg::with_name("head")
+        .long("head")
+        .value_name("file")
+        .help("HTML to use in place of the built-in page head")
+        .takes_value(true);
+
+    let tail = Arg::with_name("tail")
+        .long("tail")
+        .value_name("file")
+        .help("HTML to use in place of the built-in page tail")
+        .takes_value(true);
+
+    let theme_dir = Arg::with_name("theme_dir")
+        .long("theme-dir")
+        .env("MDSERVE_THEME")
+        .value_name("dir")
+        .help("Theme directory replacing the built-in templates and assets")
+        .takes_value(true);
+
+    let edit = Arg::with_name("edit")
+        .long("edit")
+        .help("Accept uploads from accounts in the users file");
+
+    let upload_dir = Arg::with_name("upload_dir")
+        .long("upload-dir")
+        .env("MDSERVE_UPLOAD_DIR")
+        .value_name("dir")
+        .help("Where uploads go, relative to base_dir")
+        .default_value("uploads")
+        .takes_value(true);
+
+    let pandoc = Arg::with_name("pandoc")
+        .long("pandoc")
+        .env("MDSERVE_PANDOC")
+        .value_name("path")
+        .help("pandoc executable, enabling ?format=docx|odt|epub")
+        .takes_value(true);
+
+    let content_source = Arg::with_name("source")
+        .long("source")
+        .env("MDSERVE_SOURCE")
+        .value_name("source")
+        .help("Serve a copy of git:<repository>[#<revision>], s3://<bucket>[/<prefix>] or file:<directory>, kept in --dir")
+        .conflicts_with("edit")
+        .takes_value(true);
+
+    let follow_symlinks = Arg::with_name("follow_symlinks")
+        .long("follow-symlinks")
+        .env("MDSERVE_FOLLOW_SYMLINKS")
+        .value_name("policy")
+        .help("Which symbolic links to follow")
+        .possible_values(&["never", "within-root", "always"])
+        .default_value("within-root")
+        .global(true)
+        .takes_value(true);
+
+    let auth_header = Arg::with_name("auth_header")
+        .long("auth-header")
+        .value_name("header")
+        .help("Take the user from this header when set by a trusted proxy")
+        .takes_value(true);
+
+    let auth_groups_header = Arg::with_name("auth_groups_header")
+        .long("auth-groups-header")
+        .value_name("header")
+        .help("Comma separated groups of the user, set by the proxy")
+        .requires("auth_header")
+        .takes_value(true);
+
+    let trusted_proxy = Arg::with_name("trusted_proxy")
+        .long("trusted-proxy")
+        .value_name("cidr")
+        .help("Address range of the proxies whose auth header is trusted")
+        .multiple(true)
+        .number_of_values(1)
+        .default_value("127.0.0.1/32")
+        .takes_value(true);
+
+    let oidc_key = Arg::with_name("oidc_key")
+        .long("oidc-key")
+        .value_name("pem")
+        .help("Public key checking OIDC bearer tokens (RS256)")
+        .takes_value(true);
+
+    let oidc_issuer = Arg::with_name("oidc_issuer")
+        .long("oidc-issuer")
+        .value_name("url")
+        .help("Issuer bearer tokens must come from")
+        .requires("oidc_key")
+        .takes_value(true);
+
+    let oidc_audience = Arg::with_name("oidc_audience")
+        .long("oidc-audience")
+        .value_name("id")
+        .help("Audience bearer tokens must be for")
+        .requires("oidc_key")
+        .takes_value(true);
+
+    let vhost = Arg::with_name("vhost")
+        .long("vhost")
+        .value_name("host=dir")
+        .help("Serve dir to requests for host, may be repeated")
+        .multiple(true)
+        .number_of_values(1)
+        .takes_value(true);
+
+    let config = Arg::with_name("config")
+        .short("c")
+        .long("config")
+        .env("MDSERVE_CONFIG")
+        .value_name("file")
+        .help("Configuration file (TOML)")
+        .global(true)
+        .takes_value(true);
+
+    let print_address = Arg::with_name("print_address")
+        .long("print-address")
+        .value_name("format")
+        .help("How to tell where we listen, json for one line with address, url and pid")
+        .possible_values(&["text", "json"])
+        .default_value("text")
+        .takes_value(true);
+
+    let open = Arg::with_name("open")
+        .long("open")
+        .help("Open the served site in the default browser");
+
+    let gfm = Arg::with_name("gfm")
+        .long("gfm")
+        .help("Render like GitHub Flavored Markdown");
+
+    let users = Arg::with_name("users")
+        .long("users")
+        .env("MDSERVE_USERS")
+        .value_name("file")
+        .help("Accounts file (user:bcrypt-hash:groups) for protected pages")
+        .takes_value(true);
+
+    App::new("mdserve")
+        .version("0.1")
+        .about("Serve you some markdown")
+        .arg(base_dir)
+        .arg(addr)
+        .arg(default_lang)
+        .arg(drafts)
+        .arg(users)
+        .arg(recent_widget)
+        .arg(book)
+        .arg(gfm)
+        .arg(config)
+        .arg(no_smart)
+        .arg(head)
+        .arg(tail)
+        .arg(theme_dir)
+        .arg(edit)
+        .arg(upload_dir)
+        .arg(pandoc)
+        .arg(content_source)
+        .arg(follow_symlinks)
+        .arg(vhost)
+        .arg(auth_header)
+        .arg(auth_groups_header)
+        .arg(trusted_proxy)
+        .arg(oidc_key)
+        .arg(oidc_issuer)
+        .arg(oidc_audience)
+        .arg(open)
+        .arg(print_address)
+        .subcommand(
+            SubCommand::with_name("lint")
+                .about("Check the documents for common problems")
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("format")
+                        .possible_values(&["text", "json", "github"])
+                        .default_value("text")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("doctor")
+                .about("Check the setup given by the other options before serving"),
+        )
+        .subcommand(
+            SubCommand::with_name("build")
+                .about("Export the documents as static HTML")
+                .arg(
+                    Arg::with_name("out")
+                        .long("out")
+                        .short("o")
+                        .value_name("dir")
+                        .help("Where the site goes")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("watch")
+                        .long("watch")
+                        .help("Keep exporting what changes"),
+                )
+                .arg(
+                    Arg::with_name("single_file")
+                        .long("single-file")
+                        .value_name("page")
+                        .help("Export only this page, with its styles and images inlined")
+                        .conflicts_with("watch")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("graph")
+                .about("Print the graph of links between the documents")
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("format")
+                        .possible_values(&["json", "dot"])
+                        .default_value("json")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("completions")
+                .about("Print a completion script for a shell")
+                .arg(
+                    Arg::with_name("shell")
+                        .possible_values(&["bash", "zsh", "fish"])
+                        .required(true),
+                ),
+        )
+        .subcommand(SubCommand::with_name("man").about("Print the manual page"))
+}
+
+fn main() {
+    let matches = app().get_matches();
+
+    if let Some(sub) = matches.subcommand_matches("completions") {
+        let shell = match sub.value_of("shell") {
+            Some("zsh") => clap::Shell::Zsh,
+            Some("fish") => clap::Shell::Fish,
+            _ => clap::Shell::Bash,
+        };
+        app().gen_completions_to("mdserve", shell, &mut ::std::io::stdout());
+        return;
+    }
+    if matches.subcommand_matches("man").is_some() {
+        print!("{}", man::page(app()));
+        return;
+    }
+    if let Some(matches) = matches.subcommand_matches("lint") {
+        ::std::process::exit(lint_tree(matches));
+    }
+    if let Some(sub) = matches.subcommand_matches("build") {
+        ::std::process::exit(build_site(&matches, sub));
+    }
+    if let Some(sub) = matches.subcommand_matches("graph") {
+        ::std::process::exit(graph_tree(&matches, sub));
+    }
+    if let Some(sub) = matches.subcommand_matches("doctor") {
+        ::std::process::exit(doctor_check(&matches, sub));
+    }
+
+    let argv0 = matches.value_of("base_dir");
+    let argv1 = matches.value_of("address");
+    let default_lang = matches.value_of("default_lang").unwrap_or("en");
+
+    match (argv0, argv1) {
+        (Some(base_dir), Some(addr)) => {
+            let addr: std::net::SocketAddr = match addr.parse() {
+                Ok(addr) => addr,
+                Err(_) => {
+                    eprintln!("{}: not an address, like 127.0.0.1:8000", addr);
+                    ::std::process::exit(2);
+                }
+            };
+            if let Some(spec) = matches.value_of("source") {
+                let mirrored = source::parse(spec)
+                    .and_then(|source| source::Mirror::new(source, Path::new(base_dir), spec))
+                    .and_then(|mut mirror| mirror.sync().map(|_| mirror));
+                match mirrored {
+                    Ok(mirror) => source::keep_synced(mirror),
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        ::std::process::exit(1);
+                    }
+                }
+            }
+            let book = if matches.is_present("mdbook") {
+                Some(mdbook::load(Path::new(base_dir)).expect("not an mdBook project"))
+            } else {
+                None
+            };
+            let theme_dir = matches.value_of("theme_dir").map(PathBuf::from);
+            let base_dir = book
+                .as_ref()
+                .map(|b| b.src.clone())
+                .unwrap_or_else(|| PathBuf::from(base_dir));
+            let sources = settings::Sources {
+                base_dir: base_dir.clone(),
+                config: matches.value_of("config").map(PathBuf::from),
+                users: matches.value_of("users").map(PathBuf::from),
+                gfm: matches.is_present("gfm"),
+                no_smart: matches.is_present("no_smart"),
+                theme_dir: theme_dir.clone(),
+                head: matches.value_of("head").map(PathBuf::from),
+                tail: matches.value_of("tail").map(PathBuf::from),
+            };
+            let settings = settings::load(&sources).expect("could not load settings");
+            let tuning = settings.server.clone();
+            let ctx = Context {
+                base_dir,
+                cache: ::std::sync::Arc::new(Mutex::new(HashMap::new())),
+                default_lang: default_lang.to_lowercase(),
+                drafts: matches.is_present("drafts"),
+                users: settings.users.clone(),
+                index: ::std::sync::Arc::new(Mutex::new(HashMap::new())),
+                recent_widget: matches
+                    .value_of("recent_widget")
+                    .map(|n| n.parse().expect("not a valid count")),
+                book: book.map(::std::sync::Arc::new),
+                options: settings.options.clone(),
+                static_cache: settings.static_cache.clone(),
+                templates: settings.templates.clone(),
+                theme_dir,
+                edit: matches.is_present("edit"),
+                upload_dir: PathBuf::from(matches.value_of("upload_dir").unwrap_or("uploads")),
+                pandoc: matches.value_of("pandoc").map(PathBuf::from),
+                stats: ::std::sync::Arc::new(stats::Stats::default()),
+                events: ::std::sync::Arc::new(events::Events::default()),
+                jobs: ::std::sync::Arc::new(jobs::Jobs::default()),
+                page_cache: settings.page_cache.clone(),
+                sources: ::std::sync::Arc::new(sources),
+                redirects: settings.redirects.clone(),
+                access: settings.access.clone(),
+                comments: settings.comments.clone(),
+                analytics: settings.analytics.clone(),
+                last_modified: settings.last_modified.clone(),
+                schema: settings.schema.clone(),
+                settings: ::std::sync::Arc::new(::std::sync::RwLock::new(settings)),
+                overrides: None,
+                symlinks: symlink_policy(&matches),
+                vhosts: ::std::sync::Arc::new(HashMap::new()),
+                upstream: ::std::sync::Arc::new(upstream(&matches)),
+                remote: None,
+                host: None,
+            };
+            let vhosts = matches
+                .values_of("vhost")
+                .into_iter()
+                .flatten()
+                .map(|spec| {
+                    let mut parts = spec.splitn(2, '=');
+                    let name = parts.next().unwrap_or_default().to_lowercase();
+                    let dir = match parts.next() {
+                        Some(dir) if !name.is_empty() && !dir.is_empty() => dir,
+                        _ => {
+                            eprintln!(
+                                "{}: not a virtual host, like docs.example.com=/srv/docs",
+                                spec
+                            );
+                            ::std::process::exit(2);
+                        }
+                    };
+                    (name, vhost(&ctx, PathBuf::from(dir)))
+                })
+                .collect();
+            let ctx = Context {
+                vhosts: ::std::sync::Arc::new(vhosts),
+                ..ctx
+            };
             let mut rt = tokio::runtime::Runtime::new().unwrap();
-            rt.block_on(serve(String::from(base_dir), String::from(addr)));
+            rt.block_on(serve(
+                ctx,
+                addr,
+                tuning,
+                matches.is_present("open"),
+                matches.value_of("print_address") == Some("json"),
+            ));
         }
-        _ => {
-            println!("args didnt work {:?}, {:?}", argv0, argv1);
-            ()
+        (base_dir, addr) => {
+            let missing: Vec<&str> = [
+                (base_dir, "a directory, with --dir or MDSERVE_DIR"),
+                (addr, "an address, with --address or MDSERVE_ADDRESS"),
+            ]
+            .iter()
+            .filter(|(given, _)| given.is_none())
+            .map(|(_, what)| *what)
+            .collect();
+            eprintln!("mdserve needs {}", missing.join(" and "));
+            eprintln!("{}", matches.usage());
+            ::std::process::exit(2);
         }
     }
 }
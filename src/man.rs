@@ -0,0 +1,41 @@
+use clap::App;
+
+/// roff takes a line starting with `.` or `'` for a request.
+fn escape(line: &str) -> String {
+    let line = line.replace('\\', "\\\\").replace('-', "\\-");
+    if line.starts_with('.') || line.starts_with('\'') {
+        format!("\\&{}", line)
+    } else {
+        line
+    }
+}
+
+/// A manual page made of the long help clap writes for `app`, so it
+/// lists every option there is.
+pub fn page(mut app: App) -> String {
+    let mut help = Vec::new();
+    if app.write_long_help(&mut help).is_err() {
+        return String::new();
+    }
+    let help = String::from_utf8_lossy(&help);
+    let mut out = format!(
+        ".TH MDSERVE 1 \"\" \"mdserve {}\" \"User Commands\"\n\
+         .SH NAME\n\
+         mdserve \\- serve you some markdown\n\
+         .SH DESCRIPTION\n\
+         .nf\n",
+        env!("CARGO_PKG_VERSION")
+    );
+    for line in help.lines() {
+        out.push_str(&escape(line));
+        out.push('\n');
+    }
+    out.push_str(
+        ".fi\n\
+         .SH ENVIRONMENT\n\
+         An option showing an env variable takes its value from it when not given.\n\
+         .SH SEE ALSO\n\
+         \\fBmdserve <subcommand> \\-\\-help\\fR for the options of a subcommand.\n",
+    );
+    out
+}
@@ -0,0 +1,151 @@
+use crate::listing::escape;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+#[derive(Deserialize, Default)]
+struct BookSection {
+    title: Option<String>,
+    src: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+struct BookToml {
+    #[serde(default)]
+    book: BookSection,
+}
+
+pub struct Book {
+    pub title: Option<String>,
+    /// Where the chapters live, `<root>/src` unless `book.toml` says otherwise.
+    pub src: PathBuf,
+}
+
+pub fn load(root: &Path) -> Result<Book, String> {
+    let path = root.join("book.toml");
+    let content =
+        ::std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let config: BookToml =
+        toml::from_str(&content).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(Book {
+        title: config.book.title,
+        src: root.join(config.book.src.unwrap_or_else(|| String::from("src"))),
+    })
+}
+
+/// Maps the URLs of mdBook's HTML output back onto their sources:
+/// `/intro.html` is `/intro.md`, and `index.html` stands for `README.md`.
+pub fn source_path(req_path: &str) -> String {
+    let path = if req_path.ends_with('/') {
+        format!("{}index.html", req_path)
+    } else {
+        String::from(req_path)
+    };
+    match path.strip_suffix(".html") {
+        Some(stem) if stem.ends_with("/index") => {
+            format!("{}README.md", &stem[..stem.len() - "index".len()])
+        }
+        Some(stem) => format!("{}.md", stem),
+        None => String::from(req_path),
+    }
+}
+
+pub fn chapter_url(link: &str) -> String {
+    let link = link.trim_start_matches("./");
+    let html = match link.strip_suffix(".md") {
+        Some(stem) if stem == "README" || stem.ends_with("/README") => {
+            format!("{}index.html", &stem[..stem.len() - "README".len()])
+        }
+        Some(stem) => format!("{}.html", stem),
+        None => String::from(link),
+    };
+    format!("/{}", html)
+}
+
+struct Item {
+    indent: usize,
+    title: String,
+    link: String,
+}
+
+fn parse_item(line: &str) -> Option<Item> {
+    let indent = line.len() - line.trim_start().len();
+    let rest = line
+        .trim_start()
+        .trim_start_matches(|c| c == '-' || c == '*')
+        .trim();
+    let rest = rest.strip_prefix('[')?;
+    let close = rest.find("](")?;
+    let title = &rest[..close];
+    let link = rest[close + 2..].split(')').next()?;
+    Some(Item {
+        indent,
+        title: String::from(title),
+        link: String::from(link.trim()),
+    })
+}
+
+/// The chapters `SUMMARY.md` links to, in its order.
+pub fn summary_links(summary: &str) -> Vec<String> {
+    summary
+        .lines()
+        .filter_map(parse_item)
+        .map(|item| item.link)
+        .filter(|link| !link.is_empty())
+        .collect()
+}
+
+/// `SUMMARY.md` as a nested navigation list, the current chapter marked.
+pub fn sidebar(book: &Book, current: &str) -> String {
+    let summary = match ::std::fs::read_to_string(book.src.join("SUMMARY.md")) {
+        Ok(summary) => summary,
+        Err(_) => return String::new(),
+    };
+
+    let mut out = String::from("<nav class=\"summary\">\n");
+    if let Some(title) = book.title.as_ref() {
+        out.push_str(&format!("<h2><a href=\"/\">{}</a></h2>\n", escape(title)));
+    }
+    out.push_str("<ul>\n");
+    let mut stack: Vec<usize> = vec![0];
+    let mut open = false;
+    for item in summary.lines().filter_map(parse_item) {
+        if open && item.indent > *stack.last().unwrap_or(&0) {
+            stack.push(item.indent);
+            out.push_str("<ul>\n");
+        } else {
+            if open {
+                out.push_str("</li>\n");
+            }
+            while stack.len() > 1 && item.indent < *stack.last().unwrap_or(&0) {
+                stack.pop();
+                out.push_str("</ul>\n</li>\n");
+            }
+        }
+        if item.link.is_empty() {
+            out.push_str(&format!("<li class=\"draft\">{}", escape(&item.title)));
+        } else {
+            let url = chapter_url(&item.link);
+            let class = if source_path(&url) == source_path(current) {
+                " class=\"current\""
+            } else {
+                ""
+            };
+            out.push_str(&format!(
+                "<li{}><a href=\"{}\">{}</a>",
+                class,
+                escape(&url),
+                escape(&item.title)
+            ));
+        }
+        open = true;
+    }
+    if open {
+        out.push_str("</li>\n");
+    }
+    while stack.len() > 1 {
+        stack.pop();
+        out.push_str("</ul>\n</li>\n");
+    }
+    out.push_str("</ul>\n</nav>\n");
+    out
+}
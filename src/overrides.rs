@@ -0,0 +1,46 @@
+use crate::config::{self, Config};
+use std::path::Path;
+use std::time::SystemTime;
+
+pub const FILE_NAME: &str = ".mdserve.toml";
+
+/// The `.mdserve.toml` files from `base_dir` down to `dir`, merged.
+pub struct Local {
+    pub config: Config,
+    /// When the most recently changed of them changed.
+    pub modified: SystemTime,
+}
+
+pub fn load(base_dir: &Path, dir: &Path) -> Option<Local> {
+    let rel = dir.strip_prefix(base_dir).ok()?;
+    let mut dirs = vec![base_dir.to_path_buf()];
+    for component in rel.components() {
+        let next = dirs[dirs.len() - 1].join(component);
+        dirs.push(next);
+    }
+
+    let mut local: Option<Local> = None;
+    for dir in dirs {
+        let path = dir.join(FILE_NAME);
+        let modified = match ::std::fs::metadata(&path).and_then(|m| m.modified()) {
+            Ok(modified) => modified,
+            Err(_) => continue,
+        };
+        let mut config = match config::load(&path) {
+            Ok(config) => config,
+            Err(err) => {
+                eprintln!("{}", err);
+                continue;
+            }
+        };
+        config.pages.theme = config.pages.theme.map(|theme| dir.join(theme));
+        match local.as_mut() {
+            Some(local) => {
+                local.config.merge(config);
+                local.modified = local.modified.max(modified);
+            }
+            None => local = Some(Local { config, modified }),
+        }
+    }
+    local
+}
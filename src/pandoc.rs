@@ -0,0 +1,84 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+#[derive(Clone, Copy)]
+pub enum Format {
+    Docx,
+    Odt,
+    Epub,
+}
+
+impl Format {
+    pub fn parse(s: &str) -> Option<Format> {
+        match s {
+            "docx" => Some(Format::Docx),
+            "odt" => Some(Format::Odt),
+            "epub" => Some(Format::Epub),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Docx => "docx",
+            Format::Odt => "odt",
+            Format::Epub => "epub",
+        }
+    }
+
+    pub fn mime(self) -> &'static str {
+        match self {
+            Format::Docx => {
+                "application/vnd.openxmlformats-officedocument.wordprocessingml.document"
+            }
+            Format::Odt => "application/vnd.oasis.opendocument.text",
+            Format::Epub => "application/epub+zip",
+        }
+    }
+}
+
+pub struct Job {
+    pub pandoc: PathBuf,
+    pub source: PathBuf,
+    pub format: Format,
+    /// Given to pandoc when the front matter has no title of its own.
+    pub title: Option<String>,
+}
+
+/// Runs pandoc over a document. It reads the front matter itself, as a
+/// YAML metadata block, so titles, authors and dates carry over.
+pub fn run(job: &Job) -> Result<Vec<u8>, String> {
+    let input = ::std::fs::read(&job.source).map_err(|e| e.to_string())?;
+    let resources = job.source.parent().unwrap_or_else(|| Path::new("."));
+
+    let mut command = Command::new(&job.pandoc);
+    command
+        .arg("--from=markdown")
+        .arg(format!("--to={}", job.format.extension()))
+        .arg(format!("--resource-path={}", resources.display()))
+        .arg("--output=-");
+    if let Some(title) = job.title.as_ref() {
+        command.arg(format!("--metadata=title:{}", title));
+    }
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("{}: {}", job.pandoc.display(), e))?;
+
+    // pandoc reads all its input before writing, a full stdout pipe can't block us here
+    child
+        .stdin
+        .take()
+        .expect("piped stdin")
+        .write_all(&input)
+        .map_err(|e| e.to_string())?;
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    if output.status.success() {
+        Ok(output.stdout)
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).into_owned())
+    }
+}
@@ -0,0 +1,45 @@
+use comrak::nodes::{AstNode, NodeValue};
+
+/// A leisurely pace for documentation.
+const WORDS_PER_MINUTE: usize = 200;
+
+fn count(text: &[u8]) -> usize {
+    String::from_utf8_lossy(text).split_whitespace().count()
+}
+
+/// Words in the text and code spans of a document.
+pub fn words<'a>(root: &'a AstNode<'a>) -> usize {
+    root.descendants()
+        .map(|node| match node.data.borrow().value {
+            NodeValue::Text(ref t) | NodeValue::Code(ref t) => count(t),
+            NodeValue::CodeBlock(ref block) => count(&block.literal),
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Minutes it takes to read `words`, never less than one.
+pub fn minutes(words: usize) -> usize {
+    ((words + WORDS_PER_MINUTE - 1) / WORDS_PER_MINUTE).max(1)
+}
+
+/// The badge under the title, or on top when the page has no `<h1>`.
+pub fn with_badge(html: &str, words: usize) -> String {
+    let badge = format!(
+        "<p class=\"reading-time\">{} words · {} min read</p>\n",
+        words,
+        minutes(words)
+    );
+    match html.find("</h1>") {
+        Some(i) => {
+            let end = i + "</h1>".len();
+            let end = if html[end..].starts_with('\n') {
+                end + 1
+            } else {
+                end
+            };
+            [&html[..end], &badge, &html[end..]].join("")
+        }
+        None => badge + html,
+    }
+}
@@ -0,0 +1,49 @@
+use crate::index::Entry;
+use crate::listing::{escape, link};
+use chrono::{DateTime, Utc};
+
+pub const DEFAULT_LIMIT: usize = 20;
+
+/// The `limit` most recently modified documents, newest first.
+pub fn latest(entries: &[Entry], limit: usize) -> Vec<&Entry> {
+    let mut sorted: Vec<&Entry> = entries.iter().collect();
+    sorted.sort_by(|a, b| b.modified.cmp(&a.modified));
+    sorted.truncate(limit);
+    sorted
+}
+
+pub fn date(entry: &Entry) -> String {
+    DateTime::<Utc>::from(entry.modified)
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
+}
+
+pub fn page(entries: &[Entry], limit: usize) -> String {
+    let items: Vec<String> = latest(entries, limit)
+        .into_iter()
+        .map(|e| {
+            format!(
+                "<li>{} <time>{}</time>\n<p>{}</p></li>",
+                link(e),
+                date(e),
+                escape(&e.excerpt)
+            )
+        })
+        .collect();
+    format!(
+        "<h1>Recently updated</h1>\n<ul class=\"recent\">\n{}\n</ul>\n",
+        items.join("\n")
+    )
+}
+
+/// A compact list to show alongside regular pages.
+pub fn widget(entries: &[Entry], limit: usize) -> String {
+    let items: Vec<String> = latest(entries, limit)
+        .into_iter()
+        .map(|e| format!("<li>{} <time>{}</time></li>", link(e), date(e)))
+        .collect();
+    format!(
+        "<aside class=\"recent\">\n<h2><a href=\"/_recent\">Recently updated</a></h2>\n<ul>\n{}\n</ul>\n</aside>\n",
+        items.join("\n")
+    )
+}
@@ -0,0 +1,107 @@
+use crate::config;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+pub const FILE_NAME: &str = "_redirects";
+
+#[derive(Clone)]
+pub struct Rule {
+    /// A path, or a prefix when it ends with `*`.
+    from: String,
+    /// Where to go, `:splat` standing for what `*` matched.
+    to: String,
+    status: http::StatusCode,
+}
+
+impl Rule {
+    fn new(from: &str, to: &str, status: u16) -> Option<Rule> {
+        match status {
+            301 | 302 | 303 | 307 | 308 => Some(Rule {
+                from: String::from(from),
+                to: String::from(to),
+                status: http::StatusCode::from_u16(status).ok()?,
+            }),
+            _ => None,
+        }
+    }
+
+    fn target(&self, path: &str) -> Option<String> {
+        match self.from.strip_suffix('*') {
+            Some(prefix) => path
+                .strip_prefix(prefix)
+                .map(|splat| self.to.replace(":splat", splat)),
+            None if self.from == path => Some(self.to.clone()),
+            None => None,
+        }
+    }
+}
+
+/// Reads `/old /new [status]` lines, as in Netlify's `_redirects`.
+pub fn parse(content: &str) -> Vec<Rule> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let from = fields.next()?;
+            let to = fields.next()?;
+            let status = match fields.next() {
+                Some(status) => status.trim_end_matches('!').parse().ok()?,
+                None => 301,
+            };
+            let rule = Rule::new(from, to, status);
+            if rule.is_none() {
+                eprintln!("{}: ignoring {}", FILE_NAME, line);
+            }
+            rule
+        })
+        .collect()
+}
+
+/// The config's redirects, then those of the `_redirects` file at the
+/// root of the tree, read again whenever it changes.
+pub struct Redirects {
+    configured: Vec<Rule>,
+    file: PathBuf,
+    loaded: Mutex<Option<(SystemTime, Vec<Rule>)>>,
+}
+
+impl Redirects {
+    pub fn new(configured: &[config::Redirect], file: PathBuf) -> Redirects {
+        Redirects {
+            configured: configured
+                .iter()
+                .filter_map(|r| Rule::new(&r.from, &r.to, r.status.unwrap_or(301)))
+                .collect(),
+            file,
+            loaded: Mutex::new(None),
+        }
+    }
+
+    pub fn find(&self, path: &str) -> Option<(http::StatusCode, String)> {
+        let found = self
+            .configured
+            .iter()
+            .find_map(|rule| rule.target(path).map(|to| (rule.status, to)));
+        if found.is_some() {
+            return found;
+        }
+
+        let modified = ::std::fs::metadata(&self.file)
+            .and_then(|m| m.modified())
+            .ok()?;
+        let mut loaded = self.loaded.lock().expect("redirects lock poisoned");
+        let stale = loaded.as_ref().map(|(m, _)| *m != modified).unwrap_or(true);
+        if stale {
+            let content = ::std::fs::read_to_string(&self.file).ok()?;
+            *loaded = Some((modified, parse(&content)));
+        }
+        loaded.as_ref().and_then(|(_, rules)| {
+            rules
+                .iter()
+                .find_map(|rule| rule.target(path).map(|to| (rule.status, to)))
+        })
+    }
+}
@@ -0,0 +1,308 @@
+use crate::config;
+use crate::frontmatter::FrontMatter;
+use crate::toc;
+use comrak::nodes::{AstNode, NodeValue};
+use comrak::{format_html, parse_document, Arena, ComrakOptions};
+
+/// How markdown turns into HTML: comrak's settings and what the
+/// sanitizer lets through afterwards.
+#[derive(Clone)]
+pub struct Options {
+    pub comrak: ComrakOptions,
+    pub gfm: bool,
+    pub embeds: Vec<String>,
+    /// Custom elements let through with their attributes.
+    pub elements: ::std::collections::BTreeMap<String, Vec<String>>,
+    /// What defines them.
+    pub element_scripts: Vec<String>,
+    /// Word count and reading time under the title of pages.
+    pub reading_time: bool,
+    /// The quick switcher on served pages, see `switcher`.
+    pub quick_switcher: bool,
+    /// Margin notes for signed in readers, see `annotations`.
+    pub annotations: bool,
+    /// Where code blocks naming a `file` read it from, see `snippets`.
+    pub snippets: Option<::std::path::PathBuf>,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            comrak: ComrakOptions {
+                smart: true,
+                unsafe_: true,
+                ext_superscript: true,
+                ext_autolink: true,
+                ext_table: true,
+                ext_header_ids: Some(String::new()),
+                ..ComrakOptions::default()
+            },
+            gfm: false,
+            embeds: Vec::new(),
+            elements: ::std::collections::BTreeMap::new(),
+            element_scripts: Vec::new(),
+            reading_time: false,
+            quick_switcher: false,
+            annotations: false,
+            snippets: None,
+        }
+    }
+}
+
+impl Options {
+    /// Rendering as close as we get to what GitHub shows for a file.
+    pub fn gfm() -> Options {
+        Options {
+            comrak: ComrakOptions {
+                github_pre_lang: true,
+                unsafe_: true,
+                ext_strikethrough: true,
+                ext_tagfilter: true,
+                ext_table: true,
+                ext_autolink: true,
+                ext_tasklist: true,
+                ext_footnotes: true,
+                ext_header_ids: Some(String::new()),
+                ..ComrakOptions::default()
+            },
+            gfm: true,
+            embeds: Vec::new(),
+            elements: ::std::collections::BTreeMap::new(),
+            element_scripts: Vec::new(),
+            reading_time: false,
+            quick_switcher: false,
+            annotations: false,
+            snippets: None,
+        }
+    }
+
+    /// Config on top of a preset; embeds from the config add to those allowed.
+    pub fn apply(&mut self, config: &config::Config) {
+        let markdown = &config.markdown;
+        if let Some(smart) = markdown.smart {
+            self.comrak.smart = smart;
+        }
+        if let Some(footnotes) = markdown.footnotes {
+            self.comrak.ext_footnotes = footnotes;
+        }
+        if let Some(description_lists) = markdown.description_lists {
+            self.comrak.ext_description_lists = description_lists;
+        }
+        self.embeds
+            .extend(config.sanitizer.embeds.iter().map(|h| h.to_lowercase()));
+        // a dash keeps this to custom elements, no <script> or <form> sneaking in
+        for (name, attributes) in config.sanitizer.elements.iter() {
+            let name = name.to_lowercase();
+            if name.contains('-') && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+                let attributes = attributes
+                    .iter()
+                    .map(|a| a.to_lowercase())
+                    .filter(|a| !a.starts_with("on"));
+                self.elements.entry(name).or_default().extend(attributes);
+            }
+        }
+        for script in config.sanitizer.scripts.iter() {
+            if !self.element_scripts.contains(script) {
+                self.element_scripts.push(script.clone());
+            }
+        }
+        if let Some(reading_time) = config.pages.reading_time {
+            self.reading_time = reading_time;
+        }
+        if let Some(quick_switcher) = config.pages.quick_switcher {
+            self.quick_switcher = quick_switcher;
+        }
+        if let Some(annotations) = config.pages.annotations {
+            self.annotations = annotations;
+        }
+    }
+
+    /// Page level settings from front matter, on top of the server's.
+    pub fn for_page(&self, front_matter: &FrontMatter) -> ::std::borrow::Cow<'_, Options> {
+        match front_matter.get_bool("smart") {
+            Some(smart) if smart != self.comrak.smart => {
+                let mut options = self.clone();
+                options.comrak.smart = smart;
+                ::std::borrow::Cow::Owned(options)
+            }
+            _ => ::std::borrow::Cow::Borrowed(self),
+        }
+    }
+
+    /// The scripts defining custom elements, when `html` uses one.
+    pub fn element_scripts(&self, html: &str) -> String {
+        let uses = self
+            .elements
+            .keys()
+            .any(|name| html.contains(&format!("<{}", name)));
+        if !uses {
+            return String::new();
+        }
+        self.element_scripts
+            .iter()
+            .map(|src| {
+                format!(
+                    "<script type=\"module\" src=\"{}\"></script>\n",
+                    crate::listing::escape(src)
+                )
+            })
+            .collect()
+    }
+
+    fn cleaner(&self) -> ammonia::Builder<'_> {
+        let mut builder = ammonia::Builder::default();
+        // comrak wraps footnotes in a <section>, ids carry the backlinks
+        builder.add_tags(&["section"]);
+        for (name, attributes) in self.elements.iter() {
+            builder
+                .add_tags(::std::iter::once(name.as_str()))
+                .add_tag_attributes(name.as_str(), attributes.iter().map(String::as_str));
+        }
+        if self.gfm {
+            builder
+                .add_generic_attributes(&["id"])
+                .add_tags(&["input"])
+                .add_tag_attributes("input", &["type", "checked", "disabled"])
+                .add_tag_attributes("pre", &["lang"])
+                .add_allowed_classes("section", &["footnotes"])
+                .add_allowed_classes("sup", &["footnote-ref"])
+                .add_allowed_classes("a", &["footnote-backref"]);
+        } else {
+            builder.add_generic_attributes(&["id", "class"]);
+        }
+        if !self.embeds.is_empty() {
+            builder
+                .add_tags(&["iframe", "video", "audio", "source"])
+                .add_tag_attributes(
+                    "iframe",
+                    &[
+                        "src",
+                        "width",
+                        "height",
+                        "title",
+                        "allow",
+                        "allowfullscreen",
+                        "frameborder",
+                    ],
+                )
+                .add_tag_attributes(
+                    "video",
+                    &[
+                        "src", "poster", "width", "height", "controls", "loop", "muted", "preload",
+                    ],
+                )
+                .add_tag_attributes("audio", &["src", "controls", "loop", "preload"])
+                .add_tag_attributes("source", &["src", "type"]);
+            let embeds = self.embeds.clone();
+            builder.attribute_filter(move |element, attribute, value| {
+                let checked = match element {
+                    "iframe" | "video" | "audio" | "source" => {
+                        attribute == "src" || attribute == "poster"
+                    }
+                    _ => false,
+                };
+                if !checked || embed_allowed(&embeds, value) {
+                    Some(value.into())
+                } else {
+                    None
+                }
+            });
+        }
+        builder
+    }
+}
+
+fn host(url: &str) -> Option<String> {
+    let rest = match url.find("//") {
+        Some(i)
+            if url[..i]
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == ':' || c == '+') =>
+        {
+            &url[i + 2..]
+        }
+        _ => return None,
+    };
+    let authority = rest.split(|c| c == '/' || c == '?' || c == '#').next()?;
+    let host = authority.rsplit('@').next()?.split(':').next()?;
+    Some(host.to_lowercase())
+}
+
+fn embed_allowed(embeds: &[String], src: &str) -> bool {
+    match host(src) {
+        Some(host) => embeds
+            .iter()
+            .any(|e| host == *e || host.ends_with(&format!(".{}", e))),
+        None => !src.contains(':') && embeds.iter().any(|e| e == "self"),
+    }
+}
+
+lazy_static! {
+    /// Good enough to find links, headings and paragraphs while scanning.
+    pub static ref DEFAULT: Options = Options::default();
+}
+
+pub fn render(body: &str, options: &Options) -> (String, Vec<toc::Heading>) {
+    let document = render_document(body, options);
+    (document.html, document.toc)
+}
+
+/// A rendered page with what was learnt about it on the way.
+pub struct Document {
+    pub html: String,
+    pub toc: Vec<toc::Heading>,
+    pub words: usize,
+    pub attachments: Vec<crate::links::Attachment>,
+    /// The source files its code blocks were filled from.
+    pub snippets: Vec<::std::path::PathBuf>,
+}
+
+pub fn render_document(body: &str, options: &Options) -> Document {
+    let arena = Arena::new();
+    let root = parse_document(&arena, body, &options.comrak);
+    let snippets = crate::snippets::transclude(root, options.snippets.as_deref());
+    Document {
+        toc: toc::headings(root),
+        words: crate::reading::words(root),
+        attachments: crate::links::attachments(root),
+        html: html_of(root, options),
+        snippets,
+    }
+}
+
+/// A document as a part of a bigger one: headings `shift` levels lower,
+/// down to `<h6>`, and their ids starting with `id_prefix`.
+pub fn render_chapter(
+    body: &str,
+    options: &Options,
+    shift: u32,
+    id_prefix: &str,
+) -> (String, Vec<toc::Heading>) {
+    let mut options = options.clone();
+    options.comrak.ext_header_ids = Some(String::from(id_prefix));
+    let arena = Arena::new();
+    let root = parse_document(&arena, body, &options.comrak);
+    crate::snippets::transclude(root, options.snippets.as_deref());
+    for node in root.descendants() {
+        if let NodeValue::Heading(ref mut heading) = node.data.borrow_mut().value {
+            heading.level = (heading.level + shift).min(6);
+        }
+    }
+    let toc = toc::headings(root)
+        .into_iter()
+        .map(|heading| toc::Heading {
+            id: format!("{}{}", id_prefix, heading.id),
+            ..heading
+        })
+        .collect();
+    (html_of(root, &options), toc)
+}
+
+fn html_of<'a>(root: &'a AstNode<'a>, options: &Options) -> String {
+    let mut html = Vec::new();
+    format_html(root, &options.comrak, &mut html).expect("writing to memory");
+    options
+        .cleaner()
+        .clean(&String::from_utf8_lossy(&html))
+        .to_string()
+}
@@ -0,0 +1,101 @@
+use crate::config;
+use crate::frontmatter::FrontMatter;
+use crate::listing::escape;
+use serde::Serialize;
+use serde_yaml::Value;
+
+const KINDS: &[&str] = &["string", "boolean", "number", "date", "list"];
+
+/// A schema naming only types we know of, for when the config is read.
+pub fn check(schema: &config::Schema) -> Result<(), String> {
+    match schema
+        .fields
+        .iter()
+        .find(|(_, kind)| !KINDS.contains(&kind.as_str()))
+    {
+        Some((key, kind)) => Err(format!(
+            "schema: {} is not a type for {}, use one of {}",
+            kind,
+            key,
+            KINDS.join(", ")
+        )),
+        None => Ok(()),
+    }
+}
+
+/// `2020-01-31`, possibly followed by a time.
+fn is_date(s: &str) -> bool {
+    s.get(..10)
+        .map(|day| chrono::NaiveDate::parse_from_str(day, "%Y-%m-%d").is_ok())
+        .unwrap_or(false)
+        && (s.len() == 10 || chrono::DateTime::parse_from_rfc3339(s).is_ok())
+}
+
+fn fits(value: &Value, kind: &str) -> bool {
+    match (value, kind) {
+        (Value::String(s), "date") => is_date(s),
+        (Value::String(_), "string") | (Value::String(_), "list") => true,
+        (Value::Bool(_), "boolean") => true,
+        (Value::Number(_), "number") => true,
+        (Value::Sequence(items), "list") => items.iter().all(Value::is_string),
+        _ => false,
+    }
+}
+
+/// What's wrong with a page's front matter, one message per problem.
+pub fn validate(schema: &config::Schema, front_matter: &FrontMatter) -> Vec<String> {
+    let mut violations = Vec::new();
+    for key in schema.required.iter() {
+        if front_matter.get(key).is_none() {
+            violations.push(format!("`{}` is required", key));
+        }
+    }
+    for (key, kind) in schema.fields.iter() {
+        match front_matter.get(key) {
+            Some(value) if !fits(value, kind) => {
+                violations.push(format!("`{}` should be a {}", key, kind))
+            }
+            _ => {}
+        }
+    }
+    if !schema.tags.is_empty() {
+        for tag in front_matter.get_list("tags") {
+            if !schema.tags.contains(&tag) {
+                violations.push(format!("tag `{}` is not one of the allowed tags", tag));
+            }
+        }
+    }
+    violations
+}
+
+#[derive(Serialize)]
+pub struct Violation {
+    pub path: String,
+    pub url: String,
+    pub message: String,
+}
+
+/// The admin report, pages in the order given.
+pub fn page(violations: &[Violation]) -> String {
+    if violations.is_empty() {
+        return String::from(
+            "<h1>Front matter</h1>\n<p>Every page's front matter fits the schema.</p>\n",
+        );
+    }
+    let rows: Vec<String> = violations
+        .iter()
+        .map(|v| {
+            format!(
+                "<tr><td><a href=\"{}\">{}</a></td><td>{}</td></tr>",
+                escape(&v.url),
+                escape(&v.path),
+                escape(&v.message)
+            )
+        })
+        .collect();
+    format!(
+        "<h1>Front matter</h1>\n<p>{} problems.</p>\n<table>\n<tr><th>page</th><th>problem</th></tr>\n{}\n</table>\n",
+        violations.len(),
+        rows.join("\n")
+    )
+}
@@ -0,0 +1,59 @@
+use crate::index::Entry;
+use crate::listing::{escape, link};
+
+/// Documents containing every term of the query, title matches first.
+pub fn search<'a>(entries: &'a [Entry], query: &str) -> Vec<&'a Entry> {
+    let terms: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+    if terms.is_empty() {
+        return Vec::new();
+    }
+    let mut results: Vec<(usize, &Entry)> = entries
+        .iter()
+        .filter_map(|e| {
+            let title = e.title.to_lowercase();
+            let tags = e.tags().join(" ").to_lowercase();
+            let mut score = 0;
+            for term in terms.iter() {
+                let in_title = title.contains(term.as_str());
+                if !in_title && !tags.contains(term.as_str()) && !e.text.contains(term.as_str()) {
+                    return None;
+                }
+                if in_title {
+                    score += 1;
+                }
+            }
+            Some((score, e))
+        })
+        .collect();
+    results.sort_by(|a, b| b.0.cmp(&a.0));
+    results.into_iter().map(|(_, e)| e).collect()
+}
+
+pub fn page(query: &str, results: &[&Entry]) -> String {
+    let items: Vec<String> = results
+        .iter()
+        .map(|e| format!("<li>{}\n<p>{}</p></li>", link(e), escape(&e.excerpt)))
+        .collect();
+    format!(
+        "<h1>Search</h1>\n\
+         <form action=\"/_search\" method=\"get\"><input type=\"search\" name=\"q\" value=\"{}\" /></form>\n\
+         <ul class=\"results\">\n{}\n</ul>\n",
+        escape(query),
+        items.join("\n")
+    )
+}
+
+/// An OpenSearch description pointing browsers at `/_search`.
+pub fn opensearch(origin: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<OpenSearchDescription xmlns="http://a9.com/-/spec/opensearch/1.1/">
+  <ShortName>mdserve</ShortName>
+  <Description>Search the documentation</Description>
+  <InputEncoding>UTF-8</InputEncoding>
+  <Url type="text/html" method="get" template="{}/_search?q={{searchTerms}}"/>
+</OpenSearchDescription>
+"#,
+        escape(origin)
+    )
+}
@@ -0,0 +1,143 @@
+use crate::config;
+use hyper::server::conn::AddrStream;
+use hyper::service::{make_service_fn, service_fn, Service};
+use hyper::{Body, Request, Response};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Where the connection a request came on comes from, as warp's own
+/// server would have told `warp::addr::remote`.
+#[derive(Clone, Copy)]
+pub struct Remote(pub SocketAddr);
+
+pub type Routes = warp::filters::BoxedFilter<(warp::reply::Response,)>;
+
+/// `[server]` settings that make sense, told apart before binding.
+pub fn check(server: &config::Server) -> Result<(), String> {
+    match server.http2.as_deref() {
+        None | Some("auto") | Some("off") | Some("only") => {}
+        Some(other) => return Err(format!("server.http2 is auto, off or only, not {}", other)),
+    }
+    if server.max_connections == Some(0) {
+        return Err(String::from("server.max_connections can't be 0"));
+    }
+    Ok(())
+}
+
+/// One of the `max_connections`, given back when its connection closes.
+struct Slot(Arc<AtomicUsize>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn admit(open: &Arc<AtomicUsize>, max: Option<usize>) -> Option<Slot> {
+    let taken = open.fetch_add(1, Ordering::SeqCst) + 1;
+    let slot = Slot(open.clone());
+    if max.map_or(true, |max| taken <= max) {
+        Some(slot)
+    } else {
+        None
+    }
+}
+
+/// For the requests of a connection over `max_connections`.
+fn busy() -> Response<Body> {
+    let mut response = Response::new(Body::from("too many connections, try again"));
+    *response.status_mut() = http::StatusCode::SERVICE_UNAVAILABLE;
+    let headers = response.headers_mut();
+    headers.insert(
+        http::header::CONNECTION,
+        http::HeaderValue::from_static("close"),
+    );
+    headers.insert(
+        http::header::RETRY_AFTER,
+        http::HeaderValue::from_static("1"),
+    );
+    response
+}
+
+fn log(
+    host: Option<&str>,
+    remote: SocketAddr,
+    method: &http::Method,
+    path: &str,
+    response: &Response<Body>,
+    started: Instant,
+) {
+    eprintln!(
+        "{} {} {} {} {} {} {} {}",
+        chrono::Utc::now().to_rfc3339(),
+        host.unwrap_or("-"),
+        remote.ip(),
+        method,
+        path,
+        response.status(),
+        started.elapsed().as_millis(),
+        crate::stats::body_size(response)
+            .map(|bytes| bytes.to_string())
+            .unwrap_or("-".into()),
+    );
+}
+
+/// Binds `addr` with the `[server]` settings, giving the address bound
+/// and the server to run; each request is logged once answered.
+pub fn bind(
+    addr: SocketAddr,
+    settings: &config::Server,
+    routes: Routes,
+) -> Result<(SocketAddr, impl ::std::future::Future<Output = ()>), String> {
+    let open = Arc::new(AtomicUsize::new(0));
+    let max = settings.max_connections;
+    let make = make_service_fn(move |conn: &AddrStream| {
+        let remote = conn.remote_addr();
+        let slot = admit(&open, max);
+        let routes = routes.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |mut request: Request<Body>| {
+                let admitted = slot.is_some();
+                let mut service = warp::service(routes.clone());
+                async move {
+                    if !admitted {
+                        return Ok::<_, Infallible>(busy());
+                    }
+                    let started = Instant::now();
+                    let method = request.method().clone();
+                    let path = String::from(request.uri().path());
+                    let host = request
+                        .headers()
+                        .get(http::header::HOST)
+                        .and_then(|h| h.to_str().ok())
+                        .map(String::from);
+                    request.extensions_mut().insert(Remote(remote));
+                    let response = service.call(request).await?;
+                    log(host.as_deref(), remote, &method, &path, &response, started);
+                    Ok(response)
+                }
+            }))
+        }
+    });
+    let mut builder = hyper::Server::try_bind(&addr)
+        .map_err(|e| e.to_string())?
+        .http1_keepalive(settings.keep_alive.unwrap_or(true))
+        .tcp_nodelay(settings.tcp_nodelay.unwrap_or(false))
+        .tcp_keepalive(settings.tcp_keepalive.map(Duration::from_secs))
+        .http2_max_concurrent_streams(settings.max_streams);
+    match settings.http2.as_deref() {
+        Some("off") => builder = builder.http1_only(true),
+        Some("only") => builder = builder.http2_only(true),
+        _ => {}
+    }
+    let server = builder.serve(make);
+    let addr = server.local_addr();
+    Ok((addr, async move {
+        if let Err(err) = server.await {
+            eprintln!("server error: {}", err);
+        }
+    }))
+}
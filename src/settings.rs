@@ -0,0 +1,89 @@
+use crate::auth::Users;
+use crate::cache_control::Rules;
+use crate::config;
+use crate::redirects::{self, Redirects};
+use crate::render::Options;
+use crate::template::Templates;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Where the settings that may change while serving are read from,
+/// as given on the command line.
+#[derive(Clone)]
+pub struct Sources {
+    pub base_dir: PathBuf,
+    pub config: Option<PathBuf>,
+    pub users: Option<PathBuf>,
+    pub gfm: bool,
+    pub no_smart: bool,
+    pub theme_dir: Option<PathBuf>,
+    pub head: Option<PathBuf>,
+    pub tail: Option<PathBuf>,
+}
+
+/// Everything a reload swaps in at once.
+#[derive(Clone)]
+pub struct Settings {
+    pub users: Arc<Users>,
+    pub options: Arc<Options>,
+    pub static_cache: Arc<Rules>,
+    pub templates: Arc<Templates>,
+    pub page_cache: http::HeaderValue,
+    pub redirects: Arc<Redirects>,
+    pub access: Arc<Vec<config::Access>>,
+    pub comments: Arc<config::Comments>,
+    pub analytics: Arc<config::Analytics>,
+    pub last_modified: Arc<config::LastModified>,
+    pub schema: Arc<config::Schema>,
+    /// Only read at startup, a reload leaves connections as they are.
+    pub server: config::Server,
+}
+
+pub fn load(sources: &Sources) -> Result<Settings, String> {
+    let config = match sources.config.as_ref() {
+        Some(path) => config::load(path)?,
+        None => config::Config::default(),
+    };
+    let users = match sources.users.as_ref() {
+        Some(path) => Users::load(path).map_err(|e| format!("{}: {}", path.display(), e))?,
+        None => Users::default(),
+    };
+    let mut options = if sources.gfm {
+        Options::gfm()
+    } else {
+        Options::default()
+    };
+    options.apply(&config);
+    options.snippets = config.markdown.snippets.clone();
+    if sources.no_smart {
+        options.comrak.smart = false;
+    }
+    crate::schema::check(&config.schema)?;
+    crate::server::check(&config.server)?;
+    let static_cache = Rules::compile(&config.static_cache)
+        .map_err(|e| format!("invalid static_cache rule: {}", e))?;
+    let page_cache =
+        http::HeaderValue::from_str(config.pages.cache_control.as_deref().unwrap_or("no-cache"))
+            .map_err(|_| String::from("invalid pages.cache_control"))?;
+    Ok(Settings {
+        users: Arc::new(users),
+        options: Arc::new(options),
+        static_cache: Arc::new(static_cache),
+        templates: Arc::new(Templates::new(
+            sources.theme_dir.as_deref(),
+            sources.head.clone(),
+            sources.tail.clone(),
+        )),
+        page_cache,
+        redirects: Arc::new(Redirects::new(
+            &config.redirects,
+            sources.base_dir.join(redirects::FILE_NAME),
+        )),
+        access: Arc::new(config.access),
+        comments: Arc::new(config.comments),
+        analytics: Arc::new(config.analytics),
+        last_modified: Arc::new(config.last_modified),
+        schema: Arc::new(config.schema),
+        server: config.server,
+    })
+}
@@ -0,0 +1,59 @@
+use crate::listing::escape;
+use crate::render;
+
+const SLIDES_CSS: &'static str = include_str!("html/slides.css");
+const SLIDES_JS: &'static str = include_str!("html/slides.js");
+
+fn is_fence(line: &str) -> bool {
+    let line = line.trim_start();
+    line.starts_with("```") || line.starts_with("~~~")
+}
+
+fn starts_slide(line: &str) -> bool {
+    line.starts_with("# ") || line.starts_with("## ")
+}
+
+/// Cuts a markdown body into slides at `---` breaks and at level one
+/// and two headings, leaving code blocks alone.
+pub fn split(body: &str) -> Vec<String> {
+    let mut slides: Vec<String> = vec![String::new()];
+    let mut in_code = false;
+    let mut previous_blank = true;
+    for line in body.lines() {
+        if is_fence(line) {
+            in_code = !in_code;
+        }
+        let current = slides.last_mut().expect("at least one slide");
+        if !in_code && line.trim_end() == "---" && previous_blank {
+            slides.push(String::new());
+        } else if !in_code && starts_slide(line) && !current.trim().is_empty() {
+            slides.push(format!("{}\n", line));
+        } else {
+            current.push_str(line);
+            current.push('\n');
+        }
+        previous_blank = line.trim().is_empty();
+    }
+    slides.retain(|s| !s.trim().is_empty());
+    slides
+}
+
+pub fn page(title: &str, body: &str, options: &render::Options) -> String {
+    let sections: Vec<String> = split(body)
+        .iter()
+        .map(|slide| {
+            let (html, _) = render::render(slide, options);
+            format!("<section class=\"slide\">\n{}</section>", html)
+        })
+        .collect();
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"UTF-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1.0\">\n\
+         <title>{}</title>\n<style>\n{}</style>\n</head>\n<body>\n{}\n\
+         <div class=\"progress\"></div>\n<script>\n{}</script>\n</body>\n</html>\n",
+        escape(title),
+        SLIDES_CSS,
+        sections.join("\n"),
+        SLIDES_JS
+    )
+}
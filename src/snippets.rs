@@ -0,0 +1,173 @@
+use comrak::nodes::{AstNode, NodeValue};
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+/// What a fence asks for in the words after its language, as in
+/// ```` ```rust file=src/lib.rs lines=10-40 ```` or `region=setup`.
+struct Request {
+    file: String,
+    lines: Option<(usize, Option<usize>)>,
+    region: Option<String>,
+}
+
+fn parse(info: &str) -> Option<Request> {
+    let mut request = Request {
+        file: String::new(),
+        lines: None,
+        region: None,
+    };
+    for word in info.split_whitespace().skip(1) {
+        let mut parts = word.splitn(2, '=');
+        let (key, value) = (parts.next()?, parts.next().unwrap_or(""));
+        match key {
+            "file" => request.file = String::from(value),
+            "lines" => request.lines = Some(range(value)?),
+            "region" => request.region = Some(String::from(value)),
+            _ => {}
+        }
+    }
+    if request.file.is_empty() {
+        None
+    } else {
+        Some(request)
+    }
+}
+
+/// `10-40`, `10-` to the end, or `10` alone; lines count from 1.
+fn range(value: &str) -> Option<(usize, Option<usize>)> {
+    let mut bounds = value.splitn(2, '-');
+    let start: usize = bounds.next()?.parse().ok().filter(|n| *n > 0)?;
+    let end = match bounds.next() {
+        None => Some(start),
+        Some("") => None,
+        Some(end) => Some(end.parse().ok().filter(|n| *n >= start)?),
+    };
+    Some((start, end))
+}
+
+/// Between the lines saying `ANCHOR: name` and `ANCHOR_END: name`, as
+/// mdBook has them, leaving out the lines marking other regions.
+fn region<'a>(lines: &[&'a str], name: &str) -> Option<Vec<&'a str>> {
+    let start = format!("ANCHOR: {}", name);
+    let end = format!("ANCHOR_END: {}", name);
+    let from = lines.iter().position(|l| l.trim_end().ends_with(&start))?;
+    let to = from
+        + lines[from..]
+            .iter()
+            .position(|l| l.trim_end().ends_with(&end))?;
+    Some(
+        lines[from + 1..to]
+            .iter()
+            .filter(|l| !l.contains("ANCHOR: ") && !l.contains("ANCHOR_END: "))
+            .cloned()
+            .collect(),
+    )
+}
+
+/// Lines without the indentation they all share.
+fn dedent(lines: &[&str]) -> String {
+    let indent = lines
+        .iter()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.len() - l.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    let mut out = String::new();
+    for line in lines {
+        out.push_str(
+            line.get(indent..)
+                .unwrap_or_else(|| line.trim_start())
+                .trim_end(),
+        );
+        out.push('\n');
+    }
+    out
+}
+
+/// `file` under `root`, never above it nor through a link leading out.
+fn locate(root: &Path, file: &str) -> Result<PathBuf, String> {
+    let rel = Path::new(file);
+    let plain = rel.components().all(|c| match c {
+        Component::Normal(_) | Component::CurDir => true,
+        _ => false,
+    });
+    if !plain {
+        return Err(String::from("paths are relative to the snippet directory"));
+    }
+    let path = root.join(rel).canonicalize().map_err(|e| e.to_string())?;
+    let root = root.canonicalize().map_err(|e| e.to_string())?;
+    if path.starts_with(&root) {
+        Ok(path)
+    } else {
+        Err(String::from("outside of the snippet directory"))
+    }
+}
+
+fn snippet(root: &Path, request: &Request) -> Result<(PathBuf, String), String> {
+    let path = locate(root, &request.file)?;
+    let text = crate::encoding::read_to_string(&path).map_err(|e| e.to_string())?;
+    let lines: Vec<&str> = text.lines().collect();
+    let lines = match request.region.as_ref() {
+        Some(name) => region(&lines, name).ok_or_else(|| format!("no region {}", name))?,
+        None => lines,
+    };
+    let lines = match request.lines {
+        Some((start, end)) => {
+            let end = end.unwrap_or(lines.len()).min(lines.len());
+            if start > end {
+                return Err(format!("no line {}, {} lines", start, lines.len()));
+            }
+            &lines[start - 1..end]
+        }
+        None => &lines[..],
+    };
+    Ok((path, dedent(lines)))
+}
+
+/// Fills the code blocks naming a `file` with its content, lines and
+/// regions picked out, or with why that failed; gives the files read.
+/// Without a snippet directory, blocks are left as written.
+pub fn transclude<'a>(root: &'a AstNode<'a>, dir: Option<&Path>) -> Vec<PathBuf> {
+    let dir = match dir {
+        Some(dir) => dir,
+        None => return Vec::new(),
+    };
+    let mut files = Vec::new();
+    for node in root.descendants() {
+        if let NodeValue::CodeBlock(ref mut block) = node.data.borrow_mut().value {
+            let request = match parse(&String::from_utf8_lossy(&block.info)) {
+                Some(request) => request,
+                None => continue,
+            };
+            block.literal = match snippet(dir, &request) {
+                Ok((path, code)) => {
+                    files.push(path);
+                    code.into_bytes()
+                }
+                Err(err) => {
+                    // watched all the same, for when it shows up
+                    files.push(dir.join(&request.file));
+                    format!("snippet {}: {}\n", request.file, err).into_bytes()
+                }
+            };
+        }
+    }
+    files
+}
+
+/// Each file with its mtime now, to tell later on whether it changed.
+pub fn stamp(files: Vec<PathBuf>) -> Vec<(PathBuf, Option<SystemTime>)> {
+    files
+        .into_iter()
+        .map(|path| {
+            let modified = ::std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+            (path, modified)
+        })
+        .collect()
+}
+
+pub fn changed(stamps: &[(PathBuf, Option<SystemTime>)]) -> bool {
+    stamps.iter().any(|(path, modified)| {
+        ::std::fs::metadata(path).and_then(|m| m.modified()).ok() != *modified
+    })
+}
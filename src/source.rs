@@ -0,0 +1,442 @@
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, SystemTime};
+
+/// How often a mirror asks its source what changed.
+const REFRESH: Duration = Duration::from_secs(30);
+/// Marks a directory as a copy of a source, that syncing may empty.
+const MARKER: &str = ".mdserve-source";
+
+/// A file of a source, with something telling its versions apart.
+pub struct Item {
+    pub path: PathBuf,
+    pub version: String,
+}
+
+/// Where documents come from when not from the served directory itself.
+/// Sources are read-only, they are copied over by a `Mirror` to be served,
+/// and everything reads the copy.
+pub trait ContentSource: Send {
+    /// Every file, paths relative to the root of the content.
+    fn list(&self) -> Result<Vec<Item>, String>;
+    fn read(&self, item: &Item) -> Result<Vec<u8>, String>;
+}
+
+/// Paths that stay below the directory they're copied to.
+fn is_relative(path: &Path) -> bool {
+    path.components().all(|c| match c {
+        Component::Normal(_) => true,
+        _ => false,
+    }) && path.components().next().is_some()
+}
+
+/// Every file below `dir`, dot files too.
+fn files(dir: &Path, found: &mut Vec<PathBuf>) {
+    let entries = match ::std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        match entry.metadata() {
+            Ok(meta) if meta.is_dir() => files(&path, found),
+            Ok(meta) if meta.is_file() => found.push(path),
+            _ => {}
+        }
+    }
+}
+
+/// Another directory, e.g. a network mount, taken as it is at each sync.
+pub struct Fs {
+    pub dir: PathBuf,
+}
+
+impl ContentSource for Fs {
+    fn list(&self) -> Result<Vec<Item>, String> {
+        if !self.dir.is_dir() {
+            return Err(format!("{}: not a directory", self.dir.display()));
+        }
+        let mut found = Vec::new();
+        files(&self.dir, &mut found);
+        Ok(found
+            .into_iter()
+            .filter_map(|path| {
+                let modified = ::std::fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+                let stamp = modified.duration_since(SystemTime::UNIX_EPOCH).ok()?;
+                Some(Item {
+                    path: path.strip_prefix(&self.dir).ok()?.to_path_buf(),
+                    version: stamp.as_nanos().to_string(),
+                })
+            })
+            .collect())
+    }
+
+    fn read(&self, item: &Item) -> Result<Vec<u8>, String> {
+        let path = self.dir.join(&item.path);
+        ::std::fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+/// A revision of a git repository, bare or not; blobs are their own version.
+pub struct Git {
+    pub repo: PathBuf,
+    pub rev: String,
+}
+
+impl Git {
+    fn git(&self) -> Command {
+        let mut command = Command::new("git");
+        // -C finds the repository there, bare or a checkout
+        command.arg("-C").arg(&self.repo);
+        command
+    }
+}
+
+fn run(mut command: Command, what: &str) -> Result<Vec<u8>, String> {
+    let output = command.output().map_err(|e| format!("{}: {}", what, e))?;
+    if output.status.success() {
+        Ok(output.stdout)
+    } else {
+        Err(format!(
+            "{}: {}",
+            what,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+impl ContentSource for Git {
+    fn list(&self) -> Result<Vec<Item>, String> {
+        let mut command = self.git();
+        command
+            .args(&["ls-tree", "-r", "-z", "--full-tree"])
+            .arg(&self.rev);
+        let listing = run(command, "git ls-tree")?;
+        // <mode> <type> <object>\t<path>, NUL terminated
+        Ok(listing
+            .split(|b| *b == 0)
+            .filter_map(|entry| {
+                let entry = ::std::str::from_utf8(entry).ok()?;
+                let (meta, path) = entry.split_at(entry.find('\t')?);
+                let mut meta = meta.split(' ');
+                let (_, kind, object) = (meta.next()?, meta.next()?, meta.next()?);
+                if kind != "blob" {
+                    return None;
+                }
+                Some(Item {
+                    path: PathBuf::from(&path[1..]),
+                    version: String::from(object),
+                })
+            })
+            .collect())
+    }
+
+    fn read(&self, item: &Item) -> Result<Vec<u8>, String> {
+        let mut command = self.git();
+        command.args(&["cat-file", "blob"]).arg(&item.version);
+        run(command, "git cat-file")
+    }
+}
+
+/// A bucket of an S3-compatible store, read with curl and signed with the
+/// usual `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY` when they're set.
+pub struct S3 {
+    /// Like `https://s3.amazonaws.com`, buckets addressed by path.
+    pub endpoint: String,
+    pub region: String,
+    pub bucket: String,
+    pub prefix: String,
+}
+
+const KEY: &percent_encoding::AsciiSet = &percent_encoding::NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~')
+    .remove(b'/');
+
+/// A quoted value for curl's config file, backslashes and quotes escaped.
+fn quoted(s: &str) -> String {
+    let escaped = s
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r");
+    format!("\"{}\"", escaped)
+}
+
+fn unescape_xml(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+lazy_static! {
+    static ref CONTENTS: regex::Regex =
+        regex::Regex::new(r"(?s)<Contents>(.*?)</Contents>").unwrap();
+    static ref OBJECT_KEY: regex::Regex = regex::Regex::new(r"<Key>(.*?)</Key>").unwrap();
+    static ref ETAG: regex::Regex = regex::Regex::new(r"<ETag>(.*?)</ETag>").unwrap();
+    static ref NEXT: regex::Regex =
+        regex::Regex::new(r"<NextContinuationToken>(.*?)</NextContinuationToken>").unwrap();
+}
+
+impl S3 {
+    /// GETs `url`, credentials going through curl's stdin rather than its
+    /// command line, where anyone may read them.
+    fn get(&self, url: &str, query: &[(&str, &str)]) -> Result<Vec<u8>, String> {
+        let mut command = Command::new("curl");
+        command.args(&[
+            "--silent",
+            "--show-error",
+            "--fail",
+            "--get",
+            "--config",
+            "-",
+        ]);
+        for (name, value) in query {
+            command
+                .arg("--data-urlencode")
+                .arg(format!("{}={}", name, value));
+        }
+        let mut config = String::new();
+        if let (Ok(id), Ok(secret)) = (
+            ::std::env::var("AWS_ACCESS_KEY_ID"),
+            ::std::env::var("AWS_SECRET_ACCESS_KEY"),
+        ) {
+            command
+                .arg("--aws-sigv4")
+                .arg(format!("aws:amz:{}:s3", self.region));
+            config.push_str(&format!(
+                "user = {}\n",
+                quoted(&format!("{}:{}", id, secret))
+            ));
+            if let Ok(token) = ::std::env::var("AWS_SESSION_TOKEN") {
+                let header = format!("x-amz-security-token: {}", token);
+                config.push_str(&format!("header = {}\n", quoted(&header)));
+            }
+        }
+        let mut child = command
+            .arg(url)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("curl: {}", e))?;
+        child
+            .stdin
+            .take()
+            .expect("piped stdin")
+            .write_all(config.as_bytes())
+            .map_err(|e| e.to_string())?;
+        let output = child.wait_with_output().map_err(|e| e.to_string())?;
+        if output.status.success() {
+            Ok(output.stdout)
+        } else {
+            Err(format!(
+                "{}: {}",
+                url,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))
+        }
+    }
+
+    /// The prefix as a directory, so `docs` doesn't take in `docs-old/`.
+    fn key_prefix(&self) -> String {
+        if self.prefix.is_empty() || self.prefix.ends_with('/') {
+            self.prefix.clone()
+        } else {
+            format!("{}/", self.prefix)
+        }
+    }
+
+    fn bucket_url(&self) -> String {
+        format!("{}/{}", self.endpoint.trim_end_matches('/'), self.bucket)
+    }
+}
+
+impl ContentSource for S3 {
+    fn list(&self) -> Result<Vec<Item>, String> {
+        let mut items = Vec::new();
+        let mut token: Option<String> = None;
+        let prefix = self.key_prefix();
+        loop {
+            let mut query = vec![("list-type", "2"), ("prefix", prefix.as_str())];
+            if let Some(token) = token.as_ref() {
+                query.push(("continuation-token", token.as_str()));
+            }
+            let page = self.get(&self.bucket_url(), &query)?;
+            let page = String::from_utf8_lossy(&page);
+            for object in CONTENTS.captures_iter(&page) {
+                let key = match OBJECT_KEY.captures(&object[1]) {
+                    Some(key) => unescape_xml(&key[1]),
+                    None => continue,
+                };
+                let path = match key.strip_prefix(prefix.as_str()) {
+                    Some(path) if !path.is_empty() && !path.ends_with('/') => path,
+                    _ => continue,
+                };
+                items.push(Item {
+                    path: PathBuf::from(path.trim_start_matches('/')),
+                    version: ETAG
+                        .captures(&object[1])
+                        .map(|etag| unescape_xml(&etag[1]))
+                        .unwrap_or_default(),
+                });
+            }
+            token = NEXT.captures(&page).map(|next| unescape_xml(&next[1]));
+            if token.is_none() {
+                return Ok(items);
+            }
+        }
+    }
+
+    fn read(&self, item: &Item) -> Result<Vec<u8>, String> {
+        let prefix = self.key_prefix();
+        let key = format!(
+            "{}{}",
+            prefix,
+            item.path.to_string_lossy().replace('\\', "/")
+        );
+        let url = format!(
+            "{}/{}",
+            self.bucket_url(),
+            percent_encoding::utf8_percent_encode(&key, KEY)
+        );
+        self.get(&url, &[])
+    }
+}
+
+/// A source from `--source`: `git:<repository>[#<revision>]`,
+/// `s3://<bucket>[/<prefix>]` or `file:<directory>`.
+pub fn parse(spec: &str) -> Result<Box<dyn ContentSource>, String> {
+    if let Some(rest) = spec.strip_prefix("git:") {
+        let mut parts = rest.splitn(2, '#');
+        let repo = PathBuf::from(parts.next().unwrap_or_default());
+        let rev = String::from(parts.next().unwrap_or("HEAD"));
+        if !crate::diff::valid_rev(&rev) {
+            return Err(format!("{}: not a git revision", rev));
+        }
+        return Ok(Box::new(Git { repo, rev }));
+    }
+    if let Some(rest) = spec.strip_prefix("s3://") {
+        let mut parts = rest.splitn(2, '/');
+        let bucket = String::from(parts.next().unwrap_or_default());
+        if bucket.is_empty() {
+            return Err(format!("{}: no bucket", spec));
+        }
+        return Ok(Box::new(S3 {
+            endpoint: ::std::env::var("MDSERVE_S3_ENDPOINT")
+                .unwrap_or_else(|_| String::from("https://s3.amazonaws.com")),
+            region: ::std::env::var("AWS_REGION").unwrap_or_else(|_| String::from("us-east-1")),
+            bucket,
+            prefix: String::from(parts.next().unwrap_or_default()),
+        }));
+    }
+    if let Some(dir) = spec.strip_prefix("file:") {
+        return Ok(Box::new(Fs {
+            dir: PathBuf::from(dir),
+        }));
+    }
+    Err(format!(
+        "{}: not a source, try git:<repository>#<revision>, s3://<bucket>/<prefix> or file:<directory>",
+        spec
+    ))
+}
+
+/// Files mdserve writes next to the documents, that syncing leaves alone.
+fn is_ours(rel: &Path) -> bool {
+    rel == Path::new(MARKER)
+        || rel.file_name().map_or(false, |n| {
+            crate::annotations::is_sidecar(&n.to_string_lossy())
+        })
+}
+
+/// A directory kept a copy of a source, what gets served and cached.
+pub struct Mirror {
+    source: Box<dyn ContentSource>,
+    dir: PathBuf,
+    known: HashMap<PathBuf, String>,
+}
+
+impl Mirror {
+    /// Refuses a directory with files of its own, syncing would remove them.
+    pub fn new(source: Box<dyn ContentSource>, dir: &Path, spec: &str) -> Result<Mirror, String> {
+        let marker = dir.join(MARKER);
+        let mut found = Vec::new();
+        files(dir, &mut found);
+        if !found.is_empty() && !marker.is_file() {
+            return Err(format!(
+                "{} has files and isn't a copy of a source, give an empty directory",
+                dir.display()
+            ));
+        }
+        ::std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        ::std::fs::write(&marker, format!("{}\n", spec))
+            .map_err(|e| format!("{}: {}", marker.display(), e))?;
+        Ok(Mirror {
+            source,
+            dir: dir.to_path_buf(),
+            known: HashMap::new(),
+        })
+    }
+
+    /// Brings the copy up to date, giving back how many files changed.
+    pub fn sync(&mut self) -> Result<usize, String> {
+        let items: Vec<Item> = self
+            .source
+            .list()?
+            .into_iter()
+            .filter(|item| is_relative(&item.path) && item.path != Path::new(MARKER))
+            .collect();
+        let mut changed = 0;
+        for item in items.iter() {
+            if self.known.get(&item.path) == Some(&item.version) {
+                continue;
+            }
+            let bytes = self.source.read(item)?;
+            let target = self.dir.join(&item.path);
+            if let Some(parent) = target.parent() {
+                ::std::fs::create_dir_all(parent)
+                    .map_err(|e| format!("{}: {}", parent.display(), e))?;
+            }
+            // renamed into place, readers never see half a file
+            let partial = target.with_file_name(format!(
+                ".{}.partial",
+                target.file_name().unwrap_or_default().to_string_lossy()
+            ));
+            ::std::fs::write(&partial, bytes)
+                .and_then(|_| ::std::fs::rename(&partial, &target))
+                .map_err(|e| format!("{}: {}", target.display(), e))?;
+            self.known.insert(item.path.clone(), item.version.clone());
+            changed += 1;
+        }
+
+        let wanted: HashSet<&Path> = items.iter().map(|i| i.path.as_path()).collect();
+        let mut found = Vec::new();
+        files(&self.dir, &mut found);
+        for path in found {
+            let rel = path.strip_prefix(&self.dir).unwrap_or(&path);
+            if !is_ours(rel) && !wanted.contains(rel) {
+                if ::std::fs::remove_file(&path).is_ok() {
+                    changed += 1;
+                }
+                self.known.remove(rel);
+            }
+        }
+        Ok(changed)
+    }
+}
+
+/// Syncs the mirror every little while, for as long as we run.
+pub fn keep_synced(mut mirror: Mirror) {
+    ::std::thread::spawn(move || loop {
+        ::std::thread::sleep(REFRESH);
+        if let Err(err) = mirror.sync() {
+            eprintln!("source not synced: {}", err);
+        }
+    });
+}
@@ -0,0 +1,97 @@
+use regex::{Captures, Regex};
+use std::path::{Component, Path, PathBuf};
+
+lazy_static! {
+    static ref STYLESHEET: Regex =
+        Regex::new(r#"<link\b[^>]*\brel="stylesheet"[^>]*>"#).expect("valid regex");
+    static ref HREF: Regex = Regex::new(r#"\bhref="([^"]*)""#).expect("valid regex");
+    static ref IMG_SRC: Regex =
+        Regex::new(r#"(<img\b[^>]*\bsrc=")([^"]*)(")"#).expect("valid regex");
+}
+
+/// Where the files a page refers to are found.
+pub struct Files<'a> {
+    pub base_dir: &'a Path,
+    pub theme_dir: Option<&'a Path>,
+    /// The directory of the page, relative URLs start there.
+    pub page_dir: &'a Path,
+}
+
+impl<'a> Files<'a> {
+    /// The local file behind a URL, never one outside its root.
+    fn find(&self, url: &str) -> Option<PathBuf> {
+        if url.contains("://") || url.starts_with("//") || url.starts_with("data:") {
+            return None;
+        }
+        let url = url.split(|c| c == '?' || c == '#').next()?;
+        let url = percent_encoding::percent_decode_str(url)
+            .decode_utf8()
+            .ok()?;
+        let (root, rel) = match url.strip_prefix("/_theme/") {
+            Some(rel) => (self.theme_dir?.join("assets"), PathBuf::from(rel)),
+            None => match url.strip_prefix('/') {
+                Some(rel) => (self.base_dir.to_path_buf(), PathBuf::from(rel)),
+                None => (
+                    self.base_dir.to_path_buf(),
+                    self.page_dir.strip_prefix(self.base_dir).ok()?.join(&*url),
+                ),
+            },
+        };
+        let mut clean = PathBuf::new();
+        for component in rel.components() {
+            match component {
+                Component::Normal(c) => clean.push(c),
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    if !clean.pop() {
+                        return None;
+                    }
+                }
+                _ => return None,
+            }
+        }
+        Some(root.join(clean))
+    }
+}
+
+fn mime(path: &Path) -> &'static str {
+    match path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .as_deref()
+    {
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        Some("webp") => "image/webp",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Makes one self-contained document of a page: local stylesheets go in
+/// `<style>` elements and local images become data URIs. Remote ones are
+/// left as they are.
+pub fn inline(html: &str, files: &Files) -> String {
+    let styled = STYLESHEET.replace_all(html, |caps: &Captures| {
+        let tag = &caps[0];
+        HREF.captures(tag)
+            .and_then(|href| files.find(&href[1]))
+            .and_then(|path| ::std::fs::read_to_string(path).ok())
+            .map(|css| format!("<style>\n{}\n</style>", css))
+            .unwrap_or_else(|| String::from(tag))
+    });
+    IMG_SRC
+        .replace_all(&styled, |caps: &Captures| {
+            let src = files
+                .find(&caps[2])
+                .and_then(|path| {
+                    ::std::fs::read(&path).ok().map(|bytes| {
+                        format!("data:{};base64,{}", mime(&path), base64::encode(&bytes))
+                    })
+                })
+                .unwrap_or_else(|| String::from(&caps[2]));
+            format!("{}{}{}", &caps[1], src, &caps[3])
+        })
+        .into_owned()
+}
@@ -0,0 +1,202 @@
+use crate::listing::escape;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const TOP_PAGES: usize = 10;
+/// Paths counted one by one, requests for others only add to the total.
+const MAX_PATHS: usize = 10_000;
+
+/// Counters on the render cache since the server started.
+pub struct Stats {
+    started: Instant,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    renders: Mutex<HashMap<PathBuf, Duration>>,
+    bytes_sent: AtomicU64,
+    traffic: Mutex<HashMap<String, Served>>,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Served {
+    requests: u64,
+    bytes: u64,
+}
+
+/// The size of a response's body, when known before sending it.
+pub fn body_size(response: &warp::reply::Response) -> Option<u64> {
+    use hyper::body::HttpBody;
+
+    response
+        .headers()
+        .get(http::header::CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok()?.parse().ok())
+        .or_else(|| response.body().size_hint().exact())
+}
+
+impl Default for Stats {
+    fn default() -> Stats {
+        Stats {
+            started: Instant::now(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            renders: Mutex::new(HashMap::new()),
+            bytes_sent: AtomicU64::new(0),
+            traffic: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Render {
+    pub path: String,
+    pub render_ms: f64,
+}
+
+#[derive(Serialize)]
+pub struct Traffic {
+    pub path: String,
+    pub requests: u64,
+    pub bytes: u64,
+}
+
+#[derive(Serialize)]
+pub struct Snapshot {
+    pub entries: usize,
+    pub memory_bytes: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub hit_ratio: f64,
+    pub uptime_seconds: u64,
+    pub slowest: Vec<Render>,
+    pub bytes_sent: u64,
+    /// The paths that sent the most.
+    pub heaviest: Vec<Traffic>,
+}
+
+impl Stats {
+    pub fn hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn miss(&self, path: &Path, took: Duration) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        if let Ok(mut renders) = self.renders.lock() {
+            renders.insert(path.to_path_buf(), took);
+        }
+    }
+
+    /// Counts a response to a request for `path`, found missing or not.
+    pub fn sent(&self, path: &str, status: http::StatusCode, bytes: Option<u64>) {
+        let bytes = bytes.unwrap_or(0);
+        self.bytes_sent.fetch_add(bytes, Ordering::Relaxed);
+        if status == http::StatusCode::NOT_FOUND {
+            return;
+        }
+        if let Ok(mut traffic) = self.traffic.lock() {
+            if traffic.len() < MAX_PATHS || traffic.contains_key(path) {
+                let served = traffic.entry(String::from(path)).or_default();
+                served.requests += 1;
+                served.bytes += bytes;
+            }
+        }
+    }
+
+    pub fn snapshot(&self, base_dir: &Path, entries: usize, memory_bytes: usize) -> Snapshot {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        let mut slowest: Vec<(PathBuf, Duration)> = self
+            .renders
+            .lock()
+            .map(|r| r.iter().map(|(p, d)| (p.clone(), *d)).collect())
+            .unwrap_or_default();
+        slowest.sort_by(|a, b| b.1.cmp(&a.1));
+        slowest.truncate(TOP_PAGES);
+        let mut heaviest: Vec<Traffic> = self
+            .traffic
+            .lock()
+            .map(|t| {
+                t.iter()
+                    .map(|(path, served)| Traffic {
+                        path: path.clone(),
+                        requests: served.requests,
+                        bytes: served.bytes,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        heaviest.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.path.cmp(&b.path)));
+        heaviest.truncate(TOP_PAGES);
+        Snapshot {
+            entries,
+            memory_bytes,
+            hits,
+            misses,
+            hit_ratio: if hits + misses == 0 {
+                0.0
+            } else {
+                hits as f64 / (hits + misses) as f64
+            },
+            uptime_seconds: self.started.elapsed().as_secs(),
+            slowest: slowest
+                .into_iter()
+                .map(|(path, took)| Render {
+                    path: path
+                        .strip_prefix(base_dir)
+                        .unwrap_or(&path)
+                        .to_string_lossy()
+                        .into_owned(),
+                    render_ms: took.as_secs_f64() * 1000.0,
+                })
+                .collect(),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            heaviest,
+        }
+    }
+}
+
+pub fn page(snapshot: &Snapshot) -> String {
+    let rows: Vec<String> = snapshot
+        .slowest
+        .iter()
+        .map(|r| {
+            format!(
+                "<tr><td>{}</td><td>{:.2}</td></tr>",
+                escape(&r.path),
+                r.render_ms
+            )
+        })
+        .collect();
+    let traffic: Vec<String> = snapshot
+        .heaviest
+        .iter()
+        .map(|t| {
+            format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape(&t.path),
+                t.requests,
+                t.bytes / 1024
+            )
+        })
+        .collect();
+    format!(
+        "<h1>Cache statistics</h1>\n<ul>\n\
+         <li>entries: {}</li>\n<li>memory: {} KiB</li>\n\
+         <li>hits: {}, misses: {}, ratio: {:.1}%</li>\n<li>uptime: {} s</li>\n\
+         <li>sent: {} KiB</li>\n</ul>\n\
+         <h2>Slowest renders</h2>\n<table>\n<tr><th>page</th><th>ms</th></tr>\n{}\n</table>\n\
+         <h2>Most traffic</h2>\n<table>\n<tr><th>path</th><th>requests</th><th>KiB</th></tr>\n{}\n</table>\n",
+        snapshot.entries,
+        snapshot.memory_bytes / 1024,
+        snapshot.hits,
+        snapshot.misses,
+        snapshot.hit_ratio * 100.0,
+        snapshot.uptime_seconds,
+        snapshot.bytes_sent / 1024,
+        rows.join("\n"),
+        traffic.join("\n")
+    )
+}
@@ -0,0 +1,11 @@
+const SWITCHER_CSS: &'static str = include_str!("html/switcher.css");
+const SWITCHER_JS: &'static str = include_str!("html/switcher.js");
+
+/// The quick switcher, opened with `/` or Ctrl-K; it lists documents
+/// from `/_api/tree`, so it only belongs on served pages.
+pub fn widget() -> String {
+    format!(
+        "<style>\n{}</style>\n<script>\n{}</script>\n",
+        SWITCHER_CSS, SWITCHER_JS
+    )
+}
@@ -0,0 +1,74 @@
+use std::fs::{DirEntry, Metadata};
+use std::path::Path;
+
+/// Which symbolic links under `base_dir` may be followed.
+#[derive(Clone, Copy)]
+pub enum Policy {
+    Never,
+    /// Only those landing back inside `base_dir`.
+    WithinRoot,
+    Always,
+}
+
+impl Policy {
+    pub fn parse(s: &str) -> Option<Policy> {
+        match s {
+            "never" => Some(Policy::Never),
+            "within-root" => Some(Policy::WithinRoot),
+            "always" => Some(Policy::Always),
+            _ => None,
+        }
+    }
+}
+
+/// Whether `path`, somewhere below `base_dir`, may be served.
+/// Paths that don't exist pass, they'll be turned down later anyway.
+pub fn allowed(policy: Policy, base_dir: &Path, path: &Path) -> bool {
+    match policy {
+        Policy::Always => true,
+        Policy::Never => {
+            let rel = match path.strip_prefix(base_dir) {
+                Ok(rel) => rel,
+                Err(_) => return false,
+            };
+            let mut current = base_dir.to_path_buf();
+            rel.components().all(|component| {
+                current.push(component);
+                ::std::fs::symlink_metadata(&current)
+                    .map(|m| !m.file_type().is_symlink())
+                    .unwrap_or(true)
+            })
+        }
+        Policy::WithinRoot => match (path.canonicalize(), base_dir.canonicalize()) {
+            (Ok(target), Ok(root)) => target.starts_with(root),
+            (Err(_), _) => true,
+            (_, Err(_)) => false,
+        },
+    }
+}
+
+/// What a walk below `base_dir` takes `entry` for: the entry itself, or
+/// what it links to when `policy` lets it be followed and it isn't a
+/// directory the walk is already in. `None` to leave it out.
+pub fn metadata(policy: Policy, base_dir: &Path, entry: &DirEntry) -> Option<Metadata> {
+    if !entry.file_type().ok()?.is_symlink() {
+        return entry.metadata().ok();
+    }
+    let path = entry.path();
+    if !allowed(policy, base_dir, &path) {
+        return None;
+    }
+    let meta = ::std::fs::metadata(&path).ok()?;
+    if meta.is_dir() {
+        let target = path.canonicalize().ok()?;
+        let circles = path
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(base_dir))
+            .any(|dir| dir.canonicalize().map_or(false, |dir| dir == target));
+        if circles {
+            return None;
+        }
+    }
+    Some(meta)
+}
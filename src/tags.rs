@@ -0,0 +1,47 @@
+use crate::index::Entry;
+use crate::listing::{entry_list, escape};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, NON_ALPHANUMERIC};
+use std::collections::BTreeMap;
+
+pub fn collect(entries: &[Entry]) -> BTreeMap<String, Vec<&Entry>> {
+    let mut tags: BTreeMap<String, Vec<&Entry>> = BTreeMap::new();
+    for entry in entries {
+        for tag in entry.tags() {
+            tags.entry(tag).or_default().push(entry);
+        }
+    }
+    tags
+}
+
+pub fn decode(segment: &str) -> String {
+    percent_decode_str(segment).decode_utf8_lossy().into_owned()
+}
+
+pub fn tag_url(tag: &str) -> String {
+    format!("/_tags/{}", utf8_percent_encode(tag, NON_ALPHANUMERIC))
+}
+
+pub fn index_page(entries: &[Entry]) -> String {
+    let items: Vec<String> = collect(entries)
+        .iter()
+        .map(|(tag, pages)| {
+            format!(
+                "<li><a href=\"{}\">{}</a> ({})</li>",
+                tag_url(tag),
+                escape(tag),
+                pages.len()
+            )
+        })
+        .collect();
+    format!("<h1>Tags</h1>\n<ul>\n{}\n</ul>\n", items.join("\n"))
+}
+
+pub fn tag_page(tag: &str, entries: &[Entry]) -> Option<String> {
+    let tags = collect(entries);
+    let pages = tags.get(tag)?;
+    Some(format!(
+        "<h1>{}</h1>\n{}<p><a href=\"/_tags\">All tags</a></p>\n",
+        escape(tag),
+        entry_list(pages.iter().cloned())
+    ))
+}
@@ -0,0 +1,323 @@
+use crate::listing::escape;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+const HTML_HEAD_STR: &'static str = include_str!("html/head.html");
+const HTML_TAIL_STR: &'static str = include_str!("html/tail.html");
+const HTML_LISTING_STR: &'static str = include_str!("html/listing.html");
+
+/// Pieces of the head and tail a theme may replace one by one, with
+/// `partials/<name>.html`; templates pull them in with `{{> name}}`.
+const PARTIALS: &[(&str, &str)] = &[
+    ("header", include_str!("html/partials/header.html")),
+    ("sidebar", include_str!("html/partials/sidebar.html")),
+    ("footer", include_str!("html/partials/footer.html")),
+];
+
+lazy_static! {
+    /// Tokens of theme assets, hashed again only when a file changes.
+    static ref TOKENS: Mutex<HashMap<PathBuf, (SystemTime, String)>> = Mutex::new(HashMap::new());
+}
+
+struct Loaded {
+    modified: SystemTime,
+    content: Arc<String>,
+}
+
+/// A piece of page chrome, either compiled in or read from a file that
+/// is read again whenever it changes.
+pub enum Source {
+    Embedded(&'static str),
+    File {
+        path: PathBuf,
+        fallback: &'static str,
+        loaded: Mutex<Option<Loaded>>,
+    },
+}
+
+impl Source {
+    pub fn file(path: PathBuf, fallback: &'static str) -> Source {
+        Source::File {
+            path,
+            fallback,
+            loaded: Mutex::new(None),
+        }
+    }
+
+    pub fn get(&self) -> Arc<String> {
+        match self {
+            Source::Embedded(s) => Arc::new(String::from(*s)),
+            Source::File {
+                path,
+                fallback,
+                loaded,
+            } => {
+                let mut loaded = loaded.lock().expect("template lock poisoned");
+                let modified = ::std::fs::metadata(path).and_then(|m| m.modified()).ok();
+                let stale = match (loaded.as_ref(), modified) {
+                    (Some(l), Some(m)) => l.modified != m,
+                    (None, Some(_)) => true,
+                    (_, None) => false,
+                };
+                if stale {
+                    match ::std::fs::read_to_string(path) {
+                        Ok(content) => {
+                            *loaded = Some(Loaded {
+                                modified: modified.unwrap_or(SystemTime::UNIX_EPOCH),
+                                content: Arc::new(content),
+                            })
+                        }
+                        Err(err) => eprintln!("template {}: {}", path.display(), err),
+                    }
+                }
+                loaded
+                    .as_ref()
+                    .map(|l| l.content.clone())
+                    .unwrap_or_else(|| Arc::new(String::from(*fallback)))
+            }
+        }
+    }
+}
+
+pub struct Templates {
+    pub head: Source,
+    pub tail: Source,
+    /// Directories without an index page, see `listing::directory`.
+    pub listing: Source,
+    partials: Vec<(&'static str, Source)>,
+    theme: Option<PathBuf>,
+    /// The files behind `head` and `tail`, what alternates fall back to.
+    files: (Option<PathBuf>, Option<PathBuf>),
+    /// `--head` and `--tail`, which other themes keep.
+    given: (Option<PathBuf>, Option<PathBuf>),
+    /// What's served under `/_theme/`, the `assets` of the `--theme`.
+    assets: Option<PathBuf>,
+    alternates: Mutex<HashMap<String, Arc<Templates>>>,
+    themes: Mutex<HashMap<PathBuf, Arc<Templates>>>,
+}
+
+impl Default for Templates {
+    fn default() -> Templates {
+        Templates {
+            head: Source::Embedded(HTML_HEAD_STR),
+            tail: Source::Embedded(HTML_TAIL_STR),
+            listing: Source::Embedded(HTML_LISTING_STR),
+            partials: partials(&[]),
+            theme: None,
+            files: (None, None),
+            given: (None, None),
+            assets: None,
+            alternates: Mutex::new(HashMap::new()),
+            themes: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+/// `name` in the theme directory, if there's one and it ships that file.
+fn themed(theme: Option<&Path>, name: &str) -> Option<PathBuf> {
+    theme.map(|t| t.join(name)).filter(|p| p.is_file())
+}
+
+fn source(path: Option<PathBuf>, fallback: &'static str) -> Source {
+    path.map(|p| Source::file(p, fallback))
+        .unwrap_or(Source::Embedded(fallback))
+}
+
+/// Each partial from the first of `dirs` that has it, or the built-in one.
+fn partials(dirs: &[Option<&Path>]) -> Vec<(&'static str, Source)> {
+    PARTIALS
+        .iter()
+        .map(|(name, builtin)| {
+            let file = format!("partials/{}.html", name);
+            let path = dirs.iter().find_map(|dir| themed(*dir, &file));
+            (*name, source(path, builtin))
+        })
+        .collect()
+}
+
+impl Templates {
+    /// Explicit files win over the theme's, which win over the built-ins.
+    pub fn new(theme: Option<&Path>, head: Option<PathBuf>, tail: Option<PathBuf>) -> Templates {
+        let given = (head.clone(), tail.clone());
+        let head = head.or_else(|| themed(theme, "head.html"));
+        let tail = tail.or_else(|| themed(theme, "tail.html"));
+        Templates {
+            head: source(head.clone(), HTML_HEAD_STR),
+            tail: source(tail.clone(), HTML_TAIL_STR),
+            listing: source(themed(theme, "listing.html"), HTML_LISTING_STR),
+            partials: partials(&[theme]),
+            theme: theme.map(Path::to_path_buf),
+            files: (head, tail),
+            given,
+            assets: theme.map(|t| t.join("assets")),
+            alternates: Mutex::new(HashMap::new()),
+            themes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The templates of another theme, for directories whose
+    /// `.mdserve.toml` picks one, keeping `--head` and `--tail`. Made once
+    /// per theme, their files are then only read again as they change.
+    pub fn with_theme(&self, theme: &Path) -> Arc<Templates> {
+        let mut themes = self.themes.lock().expect("template lock poisoned");
+        themes
+            .entry(theme.to_path_buf())
+            .or_insert_with(|| {
+                Arc::new(Templates {
+                    assets: self.assets.clone(),
+                    ..Templates::new(Some(theme), self.given.0.clone(), self.given.1.clone())
+                })
+            })
+            .clone()
+    }
+
+    pub fn head(&self) -> String {
+        self.expand(&self.head.get())
+    }
+
+    pub fn tail(&self) -> String {
+        self.expand(&self.tail.get())
+    }
+
+    fn expand(&self, template: &str) -> String {
+        let expanded = self
+            .partials
+            .iter()
+            .fold(String::from(template), |out, (name, partial)| {
+                out.replace(&format!("{{{{> {}}}}}", name), &partial.get())
+            });
+        match self.assets {
+            Some(ref assets) => bust(&expanded, assets),
+            None => expanded,
+        }
+    }
+
+    /// The templates in the theme's `name` directory, for pages saying
+    /// `template: name`; what it leaves out comes from these ones.
+    pub fn named(&self, name: &str) -> Option<Arc<Templates>> {
+        let plain = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        let dir = self.theme.as_ref()?.join(name);
+        if !plain || !dir.is_dir() {
+            return None;
+        }
+        let mut alternates = self.alternates.lock().expect("template lock poisoned");
+        let alternate = alternates.entry(String::from(name)).or_insert_with(|| {
+            let head = themed(Some(&dir), "head.html").or_else(|| self.files.0.clone());
+            let tail = themed(Some(&dir), "tail.html").or_else(|| self.files.1.clone());
+            let listing = themed(Some(&dir), "listing.html")
+                .or_else(|| themed(self.theme.as_deref(), "listing.html"));
+            Arc::new(Templates {
+                head: source(head.clone(), HTML_HEAD_STR),
+                tail: source(tail.clone(), HTML_TAIL_STR),
+                listing: source(listing, HTML_LISTING_STR),
+                partials: partials(&[Some(&dir), self.theme.as_deref()]),
+                theme: self.theme.clone(),
+                files: (head, tail),
+                given: self.given.clone(),
+                assets: self.assets.clone(),
+                alternates: Mutex::new(HashMap::new()),
+                themes: Mutex::new(HashMap::new()),
+            })
+        });
+        Some(alternate.clone())
+    }
+}
+
+/// A token of the content of `path`, telling its versions apart in URLs.
+fn token(path: &Path) -> Option<String> {
+    let modified = ::std::fs::metadata(path).and_then(|m| m.modified()).ok()?;
+    let mut tokens = TOKENS.lock().expect("template lock poisoned");
+    if let Some((m, token)) = tokens.get(path) {
+        if *m == modified {
+            return Some(token.clone());
+        }
+    }
+    let bytes = ::std::fs::read(path).ok()?;
+    // the ETag of the file, without its quotes
+    let token = String::from(crate::etag::of(&bytes).trim_matches('"'));
+    tokens.insert(path.to_path_buf(), (modified, token.clone()));
+    Some(token)
+}
+
+/// Quoted `/_theme/` URLs with `?v=` and a token of the file they point
+/// to, so browsers may keep theme assets for good and still see them change.
+fn bust(html: &str, assets: &Path) -> String {
+    const THEME: &str = "/_theme/";
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(at) = rest.find(THEME) {
+        let (before, from) = rest.split_at(at);
+        out.push_str(before);
+        let quote = before.chars().last().filter(|c| *c == '"' || *c == '\'');
+        let end = quote.and_then(|q| from.find(q)).unwrap_or(THEME.len());
+        let url = &from[..end];
+        out.push_str(url);
+        let rel = Path::new(&url[THEME.len()..]);
+        let plain = !url.contains(|c| c == '?' || c == '#')
+            && rel.components().next().is_some()
+            && rel.components().all(|c| match c {
+                Component::Normal(_) => true,
+                _ => false,
+            });
+        if plain {
+            if let Some(token) = token(&assets.join(rel)) {
+                out.push_str("?v=");
+                out.push_str(&token);
+            }
+        }
+        rest = &from[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// What templates may show of the page they wrap.
+#[derive(Default)]
+pub struct Vars {
+    pub words: Option<usize>,
+    /// The attachments section, see `attachments`.
+    pub attachments: String,
+}
+
+/// The files a page refers to as a list, nothing when there are none.
+pub fn attachments(attachments: &[crate::links::Attachment]) -> String {
+    if attachments.is_empty() {
+        return String::new();
+    }
+    let items: Vec<String> = attachments
+        .iter()
+        .map(|a| {
+            let name = a.url.rsplit('/').next().unwrap_or(&a.url);
+            format!(
+                "<li class=\"{}\"><a href=\"{}\">{}</a></li>",
+                a.kind,
+                escape(&a.url),
+                escape(name)
+            )
+        })
+        .collect();
+    format!(
+        "<section class=\"attachments\">\n<h2>Attachments</h2>\n<ul>\n{}\n</ul>\n</section>\n",
+        items.join("\n")
+    )
+}
+
+/// A template with its variables filled in, left empty off pages.
+pub fn fill(template: &str, vars: &Vars) -> String {
+    let (count, minutes) = match vars.words {
+        Some(words) => (
+            words.to_string(),
+            crate::reading::minutes(words).to_string(),
+        ),
+        None => (String::new(), String::new()),
+    };
+    template
+        .replace("{{word_count}}", &count)
+        .replace("{{reading_time}}", &minutes)
+        .replace("{{attachments}}", &vars.attachments)
+}
@@ -0,0 +1,72 @@
+use comrak::nodes::{AstNode, NodeValue};
+use serde::Serialize;
+use std::collections::HashSet;
+
+#[derive(Clone, Serialize)]
+pub struct Heading {
+    pub level: u32,
+    pub text: String,
+    /// The anchor comrak gives this heading with `ext_header_ids`.
+    pub id: String,
+}
+
+/// Plain text under a node, code spans included.
+pub fn text_of<'a>(node: &'a AstNode<'a>) -> String {
+    let mut text = String::new();
+    for n in node.descendants() {
+        match n.data.borrow().value {
+            NodeValue::Text(ref t) | NodeValue::Code(ref t) => {
+                text.push_str(&String::from_utf8_lossy(t))
+            }
+            NodeValue::SoftBreak | NodeValue::LineBreak => text.push(' '),
+            _ => {}
+        }
+    }
+    text
+}
+
+/// The id a heading gets before comrak makes it unique with a suffix.
+pub fn slug(text: &str) -> String {
+    text.to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric() || *c == '_' || *c == ' ' || *c == '-')
+        .map(|c| if c == ' ' { '-' } else { c })
+        .collect()
+}
+
+// Mirrors comrak's Anchorizer so ids line up with the rendered output.
+fn anchorize(text: &str, seen: &mut HashSet<String>) -> String {
+    let id = slug(text);
+    let mut uniq = 0;
+    let anchor = loop {
+        let anchor = if uniq == 0 {
+            id.clone()
+        } else {
+            format!("{}-{}", id, uniq)
+        };
+        if !seen.contains(&anchor) {
+            break anchor;
+        }
+        uniq += 1;
+    };
+    seen.insert(anchor.clone());
+    anchor
+}
+
+pub fn headings<'a>(root: &'a AstNode<'a>) -> Vec<Heading> {
+    let mut seen = HashSet::new();
+    root.descendants()
+        .filter_map(|node| {
+            let level = match node.data.borrow().value {
+                NodeValue::Heading(ref heading) => heading.level,
+                _ => return None,
+            };
+            let text = text_of(node);
+            Some(Heading {
+                level,
+                id: anchorize(&text, &mut seen),
+                text,
+            })
+        })
+        .collect()
+}
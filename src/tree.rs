@@ -0,0 +1,91 @@
+use crate::index::Entry;
+use crate::listing::escape;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+#[derive(Serialize)]
+pub struct Document {
+    pub path: String,
+    pub url: String,
+    pub title: String,
+    pub modified: String,
+    pub tags: Vec<String>,
+}
+
+#[derive(Serialize, Default)]
+pub struct Dir {
+    pub name: String,
+    pub path: String,
+    pub documents: Vec<Document>,
+    pub dirs: Vec<Dir>,
+}
+
+impl Dir {
+    fn child(&mut self, name: &str) -> &mut Dir {
+        match self.dirs.iter().position(|d| d.name == name) {
+            Some(i) => &mut self.dirs[i],
+            None => {
+                let path = format!("{}{}/", self.path, name);
+                self.dirs.push(Dir {
+                    name: String::from(name),
+                    path,
+                    ..Dir::default()
+                });
+                self.dirs.last_mut().unwrap()
+            }
+        }
+    }
+}
+
+fn document(entry: &Entry) -> Document {
+    Document {
+        path: entry.path.to_string_lossy().replace('\\', "/"),
+        url: entry.url(),
+        title: entry.title.clone(),
+        modified: DateTime::<Utc>::from(entry.modified).to_rfc3339(),
+        tags: entry.tags(),
+    }
+}
+
+/// Arranges index entries, sorted by path, into their directory hierarchy.
+pub fn build(entries: &[Entry]) -> Dir {
+    let mut root = Dir {
+        path: String::from("/"),
+        ..Dir::default()
+    };
+    for entry in entries {
+        let mut dir = &mut root;
+        if let Some(parent) = entry.path.parent() {
+            for component in parent.iter() {
+                dir = dir.child(&component.to_string_lossy());
+            }
+        }
+        dir.documents.push(document(entry));
+    }
+    root
+}
+
+fn render(dir: &Dir, out: &mut String) {
+    out.push_str("<ul>\n");
+    for doc in dir.documents.iter() {
+        out.push_str(&format!(
+            "<li><a href=\"{}\">{}</a></li>\n",
+            escape(&doc.url),
+            escape(&doc.title)
+        ));
+    }
+    for sub in dir.dirs.iter() {
+        out.push_str(&format!("<li class=\"dir\">{}/\n", escape(&sub.name)));
+        render(sub, out);
+        out.push_str("</li>\n");
+    }
+    out.push_str("</ul>\n");
+}
+
+/// The whole site as nested lists, one level per directory.
+pub fn page(root: &Dir) -> String {
+    let mut out = String::from("<h1>Contents</h1>\n<nav class=\"site-toc\">\n");
+    render(root, &mut out);
+    out.push_str("</nav>\n");
+    out
+}
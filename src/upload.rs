@@ -0,0 +1,59 @@
+use std::path::{Path, PathBuf};
+
+pub const MAX_SIZE: u64 = 20 * 1024 * 1024;
+
+/// Keeps a file name to characters that are safe in both paths and URLs.
+pub fn sanitize(name: &str) -> String {
+    let base = Path::new(name)
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let clean: String = base
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let clean = clean.trim_start_matches('.');
+    if clean.is_empty() {
+        String::from("upload")
+    } else {
+        String::from(clean)
+    }
+}
+
+/// A path in `dir` for `name` that doesn't clobber an existing file.
+pub fn unique_path(dir: &Path, name: &str) -> PathBuf {
+    let candidate = dir.join(name);
+    if !candidate.exists() {
+        return candidate;
+    }
+    let (stem, ext) = match name.rfind('.') {
+        Some(i) if i > 0 => (&name[..i], &name[i..]),
+        _ => (name, ""),
+    };
+    (1..)
+        .map(|n| dir.join(format!("{}-{}{}", stem, n, ext)))
+        .find(|p| !p.exists())
+        .expect("some name is free")
+}
+
+fn is_image(name: &str) -> bool {
+    let name = name.to_lowercase();
+    [".png", ".jpg", ".jpeg", ".gif", ".svg", ".webp"]
+        .iter()
+        .any(|ext| name.ends_with(ext))
+}
+
+/// What to paste in a document to show or link the uploaded file.
+pub fn snippet(name: &str, url: &str) -> String {
+    if is_image(name) {
+        format!("![{}]({})", name, url)
+    } else {
+        format!("[{}]({})", name, url)
+    }
+}